@@ -4,9 +4,11 @@ use arrow::{
     error::ArrowError
 };
 use arrow_extendr::from::FromArrowRobj;
-use geoarrow::{array::LineStringArray, GeometryArrayTrait};
+use geoarrow::{
+    array::{LineStringArray, MultiLineStringArray},
+    GeometryArrayTrait
+};
 use extendr_api::prelude::*;
-use itertools::Itertools;
 use std::result::Result;
 
 pub type ErrGeoArrowRobj = ArrowError;
@@ -59,81 +61,308 @@ fn read_geoarrow_r(robj: Robj) -> Result<
     // l?et ga = ga.as_any().downcast_ref::<LineStringArray<i32>>().unwrap().clone();
 }
 
-#[extendr]  
-fn rnet_match_two_trees(x: Robj, y: Robj, distance_tolerance: f64, angle_tolerance: f64, is_projected: bool) -> Robj {
+// read a geoarrow array of linestrings or multilinestrings as features
+fn read_features(robj: Robj) -> Vec<geo_types::MultiLineString> {
+    let arr = read_geoarrow_r(robj).unwrap();
+    let arr = arr.as_any();
+    if let Some(lns) = arr.downcast_ref::<LineStringArray<i32>>() {
+        lns.iter_geo_values().map(Into::into).collect()
+    } else if let Some(lns) = arr.downcast_ref::<LineStringArray<i64>>() {
+        lns.iter_geo_values().map(Into::into).collect()
+    } else if let Some(mlns) = arr.downcast_ref::<MultiLineStringArray<i32>>() {
+        mlns.iter_geo_values().collect()
+    } else if let Some(mlns) = arr.downcast_ref::<MultiLineStringArray<i64>>() {
+        mlns.iter_geo_values().collect()
+    } else {
+        throw_r_error("geometries must be LINESTRING or MULTILINESTRING")
+    }
+}
+
+// elevations of each coordinate of each feature from a list of numeric vectors
+fn read_z(z: &Robj) -> Option<Vec<Vec<f64>>> {
+    let z = z.as_list()?;
+    Some(z.values().map(|zi| zi.as_real_vector().unwrap_or_default()).collect())
+}
+
+fn read_layers(layers: &Robj) -> Option<Vec<i64>> {
+    layers
+        .as_integer_vector()
+        .map(|l| l.into_iter().map(i64::from).collect())
+}
+
+// apply the optional vertical tolerance, elevations and layers
+fn with_levels(
+    mut options: rnetmatch::MatchOptions,
+    vertical_tolerance: &Robj,
+    x_z: &Robj,
+    y_z: &Robj,
+    x_layer: &Robj,
+    y_layer: &Robj,
+) -> rnetmatch::MatchOptions {
+    options.vertical_tolerance = vertical_tolerance.as_real();
+    options.source_z = read_z(x_z).map(Into::into);
+    options.target_z = read_z(y_z).map(Into::into);
+    options.source_layers = read_layers(x_layer).map(Into::into);
+    options.target_layers = read_layers(y_layer).map(Into::into);
+    options
+}
+
+// raise an R error when the per-feature options do not fit the features
+fn check_inputs(
+    matcher: &rnetmatch::Matcher,
+    x: &[geo_types::MultiLineString],
+    y: &[geo_types::MultiLineString],
+) {
+    if let Err(e) = matcher.check_inputs(x, y) {
+        throw_r_error(e.to_string())
+    }
+}
+
+#[extendr]
+fn rnet_match_lines(
+    x: Robj,
+    y: Robj,
+    distance_tolerance: f64,
+    angle_tolerance: f64,
+    is_projected: bool,
+    trees: &str,
+    output: &str,
+    assignment: &str,
+    vertical_tolerance: Robj,
+    x_z: Robj,
+    y_z: Robj,
+    x_layer: Robj,
+    y_layer: Robj,
+) -> Robj {
 
     let crs_type = match is_projected {
         true => rnetmatch::CrsType::Projected,
-        false => unimplemented!("Geographic CRS not yet supported.")
+        false => rnetmatch::CrsType::Geographic,
     };
 
-    let x = read_geoarrow_r(x).unwrap();
-    let y = read_geoarrow_r(y).unwrap();
+    let index_strategy = match trees {
+        "x" => rnetmatch::IndexStrategy::SourceTree,
+        "xy" => rnetmatch::IndexStrategy::TwoTrees,
+        _ => rnetmatch::IndexStrategy::Auto,
+    };
 
-    let x = x.as_any().downcast_ref::<LineStringArray<i32>>().unwrap().clone();
-    let y = y.as_any().downcast_ref::<LineStringArray<i32>>().unwrap().clone();
+    let assignment = match assignment {
+        "one-to-one" => rnetmatch::Assignment::OneToOne,
+        "many-to-one" => rnetmatch::Assignment::ManyToOne,
+        "one-to-many" => rnetmatch::Assignment::OneToMany,
+        _ => rnetmatch::Assignment::ManyToMany,
+    };
 
-    let res = rnetmatch::find_candidates(
-        x.iter_geo_values(), 
-        y.iter_geo_values(), 
-        distance_tolerance, 
-        angle_tolerance, 
-        crs_type
-    );
+    let x = read_features(x);
+    let y = read_features(y);
 
-    let (ks, js, shared_lens): (Vec<_>, Vec<_>, Vec<_>) = res
-        .into_iter()
-        .flat_map(|(k, v)| {
-            let (j, shared_len): (Vec<_>, Vec<_>) = v.into_iter().unzip();
-            let ks = vec![k; j.len()];
-            ks.into_iter()
-                .zip(j.into_iter())
-                .zip(shared_len.into_iter())
-                .map(|((k, j), shared_len)| (k, j, shared_len))
-        })
-        .multiunzip();
+    let options = rnetmatch::MatchOptions::new(distance_tolerance, angle_tolerance)
+        .crs_type(crs_type)
+        .index_strategy(index_strategy);
+    let matcher = with_levels(options, &vertical_tolerance, &x_z, &y_z, &x_layer, &y_layer).build();
+    check_inputs(&matcher, &x, &y);
 
-    data_frame!(i = ks, j = js, shared_len = shared_lens)
+    let (x, y) = (x.into_iter(), y.into_iter());
 
+    match output {
+        "metrics" => metrics_df(matcher.find_match_metrics(x, y)),
+        "segments" => segments_df(matcher.find_segment_matches(x, y)),
+        "linear" => linear_df(matcher.find_linear_references(x, y)),
+        "unmatched" => unmatched_df(matcher.find_unmatched(x, y)),
+        "path" => path_df(matcher.find_paths(x, y, rnetmatch::PathWeights::default())),
+        _ => pairs_df(matcher.find_candidates(x, y).assign(assignment)),
+    }
 }
 
-
-#[extendr]  
-fn rnet_match_one_tree(x: Robj, y: Robj, distance_tolerance: f64, angle_tolerance: f64, is_projected: bool) -> Robj {
-
+#[extendr]
+fn rnet_self_match_lines(
+    x: Robj,
+    distance_tolerance: f64,
+    angle_tolerance: f64,
+    is_projected: bool,
+    vertical_tolerance: Robj,
+    x_z: Robj,
+    x_layer: Robj,
+) -> Robj {
     let crs_type = match is_projected {
         true => rnetmatch::CrsType::Projected,
-        false => unimplemented!("Geographic CRS not yet supported.")
+        false => rnetmatch::CrsType::Geographic,
     };
 
-    let x = read_geoarrow_r(x).unwrap();
-    let y = read_geoarrow_r(y).unwrap();
+    let x = read_features(x);
 
-    let x = x.as_any().downcast_ref::<LineStringArray<i32>>().unwrap().clone();
-    let y = y.as_any().downcast_ref::<LineStringArray<i32>>().unwrap().clone();
+    let options = rnetmatch::MatchOptions::new(distance_tolerance, angle_tolerance)
+        .crs_type(crs_type);
+    // the targets are the sources
+    let matcher = with_levels(options, &vertical_tolerance, &x_z, &x_z, &x_layer, &x_layer).build();
+    check_inputs(&matcher, &x, &x);
 
-    let res = rnetmatch::find_candidates_one_tree(
-        x.iter_geo_values(), 
-        y.iter_geo_values(), 
-        distance_tolerance, 
-        angle_tolerance,
-        crs_type
-    );
+    pairs_df(matcher.find_self_matches(x.into_iter()))
+}
 
-    let (ks, js, shared_lens): (Vec<_>, Vec<_>, Vec<_>) = res
-        .into_iter()
-        .flat_map(|(k, v)| {
-            let (j, shared_len): (Vec<_>, Vec<_>) = v.into_iter().unzip();
-            let ks = vec![k; j.len()];
-            ks.into_iter()
-                .zip(j.into_iter())
-                .zip(shared_len.into_iter())
-                .map(|((k, j), shared_len)| (k, j, shared_len))
+
+// matches of 1-based `i` and `j` transferring the attributes of the
+// features of `j` to those of `i`
+fn read_transfer(i: &[i32], j: &[i32], shared_len: &[f64]) -> rnetmatch::MatchTable {
+    i.iter()
+        .zip(j)
+        .zip(shared_len)
+        .map(|((&i, &j), &shared_len)| rnetmatch::Match {
+            i: (j - 1) as usize,
+            j: (i - 1) as usize,
+            shared_len,
+            n_segments: 1,
         })
-        .multiunzip();
+        .collect()
+}
 
-    data_frame!(i = ks, j = js, shared_len = shared_lens)
+#[extendr]
+fn rnet_aggregate_numeric(
+    i: Vec<i32>,
+    j: Vec<i32>,
+    shared_len: Vec<f64>,
+    source_len: Vec<f64>,
+    target_len: Vec<f64>,
+    values: Vec<f64>,
+    how: &str,
+) -> Doubles {
+    let how = match how {
+        "extensive" => rnetmatch::NumericAggregation::Extensive,
+        "intensive" => rnetmatch::NumericAggregation::Intensive,
+        _ => throw_r_error(format!("`how` must be \"extensive\" or \"intensive\", not \"{how}\"")),
+    };
+    let table = read_transfer(&i, &j, &shared_len);
+    // NA is NaN which is ignored
+    let values = values.into_iter().map(Some).collect::<Vec<_>>();
+    rnetmatch::Aggregator::new(&table, &source_len, &target_len)
+        .numeric(&values, how)
+        .into_iter()
+        .map(|v| v.map_or(Rfloat::na(), Rfloat::from))
+        .collect()
+}
+
+#[extendr]
+fn rnet_aggregate_categorical(
+    i: Vec<i32>,
+    j: Vec<i32>,
+    shared_len: Vec<f64>,
+    source_len: Vec<f64>,
+    target_len: Vec<f64>,
+    values: Strings,
+) -> List {
+    let table = read_transfer(&i, &j, &shared_len);
+    let values = values
+        .iter()
+        .map(|v| (!v.is_na()).then(|| v.as_str().to_string()))
+        .collect::<Vec<_>>();
+    let res = rnetmatch::Aggregator::new(&table, &source_len, &target_len).categorical(&values);
+    let proportions = res.proportions.into_iter().map(|p| {
+        p.into_iter()
+            .map(|v| v.map_or(Rfloat::na(), Rfloat::from))
+            .collect::<Doubles>()
+    });
+    List::from_names_and_values(res.categories, proportions).unwrap()
+}
+
+
+// add 1 to indices for R indexing
+fn r_index(idx: impl Iterator<Item = usize>) -> Vec<i32> {
+    idx.map(|i| (i + 1) as i32).collect()
+}
+
+fn pairs_df(res: rnetmatch::MatchTable) -> Robj {
+    let (ks, js, shared_lens, n_segments) = res.to_columns();
+    data_frame!(
+        i = r_index(ks.into_iter()),
+        j = r_index(js.into_iter()),
+        shared_len = shared_lens,
+        n_segments = n_segments.into_iter().map(|n| n as i32).collect::<Vec<_>>()
+    )
+}
+
+fn metrics_df(res: Vec<rnetmatch::MatchMetrics>) -> Robj {
+    data_frame!(
+        i = r_index(res.iter().map(|m| m.i)),
+        j = r_index(res.iter().map(|m| m.j)),
+        shared_len = res.iter().map(|m| m.shared_len).collect::<Vec<_>>(),
+        min_distance = res.iter().map(|m| m.min_distance).collect::<Vec<_>>(),
+        mean_distance = res.iter().map(|m| m.mean_distance).collect::<Vec<_>>(),
+        mean_angle_diff = res.iter().map(|m| m.mean_angle_diff).collect::<Vec<_>>(),
+        source_coverage = res.iter().map(|m| m.source_coverage).collect::<Vec<_>>(),
+        target_coverage = res.iter().map(|m| m.target_coverage).collect::<Vec<_>>(),
+        n_segments = res.iter().map(|m| m.n_segments as i32).collect::<Vec<_>>()
+    )
+}
+
+fn segments_df(res: Vec<rnetmatch::SegmentMatch>) -> Robj {
+    data_frame!(
+        i = r_index(res.iter().map(|s| s.i)),
+        i_segment = r_index(res.iter().map(|s| s.source_segment)),
+        j = r_index(res.iter().map(|s| s.j)),
+        j_segment = r_index(res.iter().map(|s| s.target_segment)),
+        i_from = res.iter().map(|s| s.overlap.source_start).collect::<Vec<_>>(),
+        i_to = res.iter().map(|s| s.overlap.source_end).collect::<Vec<_>>(),
+        j_from = res.iter().map(|s| s.overlap.target_start).collect::<Vec<_>>(),
+        j_to = res.iter().map(|s| s.overlap.target_end).collect::<Vec<_>>(),
+        shared_len = res.iter().map(|s| s.overlap.length).collect::<Vec<_>>()
+    )
+}
+
+fn linear_df(res: Vec<rnetmatch::LinearReference>) -> Robj {
+    data_frame!(
+        i = r_index(res.iter().map(|r| r.i)),
+        j = r_index(res.iter().map(|r| r.j)),
+        i_from = res.iter().map(|r| r.source_from).collect::<Vec<_>>(),
+        i_to = res.iter().map(|r| r.source_to).collect::<Vec<_>>(),
+        i_from_frac = res.iter().map(|r| r.source_from_fraction).collect::<Vec<_>>(),
+        i_to_frac = res.iter().map(|r| r.source_to_fraction).collect::<Vec<_>>(),
+        j_from = res.iter().map(|r| r.target_from).collect::<Vec<_>>(),
+        j_to = res.iter().map(|r| r.target_to).collect::<Vec<_>>(),
+        j_from_frac = res.iter().map(|r| r.target_from_fraction).collect::<Vec<_>>(),
+        j_to_frac = res.iter().map(|r| r.target_to_fraction).collect::<Vec<_>>(),
+        shared_len = res.iter().map(|r| r.shared_len).collect::<Vec<_>>()
+    )
+}
+
+fn unmatched_df(res: rnetmatch::UnmatchedReport) -> Robj {
+    let rows = res
+        .source
+        .iter()
+        .map(|u| ("x", u))
+        .chain(res.target.iter().map(|u| ("y", u)))
+        .collect::<Vec<_>>();
+    // geometries are returned alongside the data frame and made into an
+    // sfc column in R
+    list!(
+        data = data_frame!(
+            side = rows.iter().map(|(side, _)| *side).collect::<Vec<_>>(),
+            index = r_index(rows.iter().map(|(_, u)| u.index)),
+            unmatched_len = rows.iter().map(|(_, u)| u.unmatched_len).collect::<Vec<_>>(),
+            coverage = rows.iter().map(|(_, u)| u.coverage).collect::<Vec<_>>()
+        ),
+        geometry = List::from_values(rows.iter().map(|(_, u)| part_coords(&u.geometry)))
+    )
+    .into()
+}
+
+// each part of `x` as its x coordinates followed by its y coordinates
+fn part_coords(x: &geo_types::MultiLineString) -> List {
+    List::from_values(x.iter().map(|part| {
+        Doubles::from_values(part.coords().map(|c| c.x).chain(part.coords().map(|c| c.y)))
+    }))
+}
 
+fn path_df(res: Vec<rnetmatch::MatchedPath>) -> Robj {
+    let rows = res
+        .iter()
+        .flat_map(|p| p.steps.iter().enumerate().map(move |(k, s)| (p.i, k, s)))
+        .collect::<Vec<_>>();
+    data_frame!(
+        i = r_index(rows.iter().map(|(i, _, _)| *i)),
+        step = r_index(rows.iter().map(|(_, k, _)| *k)),
+        j = r_index(rows.iter().map(|(_, _, s)| s.j)),
+        shared_len = rows.iter().map(|(_, _, s)| s.shared_len).collect::<Vec<_>>()
+    )
 }
 
 
@@ -142,8 +371,10 @@ fn rnet_match_one_tree(x: Robj, y: Robj, distance_tolerance: f64, angle_toleranc
 // See corresponding C code in `entrypoint.c`.
 extendr_module! {
     mod rnetmatch;
-    fn rnet_match_one_tree;
-    fn rnet_match_two_trees;
+    fn rnet_match_lines;
+    fn rnet_self_match_lines;
+    fn rnet_aggregate_numeric;
+    fn rnet_aggregate_categorical;
 }
 
 
@@ -0,0 +1,236 @@
+use crate::table::MatchTable;
+use std::collections::BTreeMap;
+
+/// How the values of source features are combined onto a target feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Quantities that are split between targets, such as counts. See
+    /// [Aggregator::extensive].
+    Extensive,
+    /// Rates or properties of a line, such as speeds. See
+    /// [Aggregator::intensive].
+    Intensive,
+    /// Categories, summarised as the proportion of each. See
+    /// [Aggregator::categorical].
+    Categorical,
+}
+
+/// How the values of a numeric column are combined onto a target feature.
+/// See [Aggregator::numeric].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericAggregation {
+    /// See [Aggregator::extensive]
+    Extensive,
+    /// See [Aggregator::intensive]
+    Intensive,
+}
+
+/// Proportion of each category among the sources matched to each target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryProportions<T> {
+    /// The categories found among the matched sources, sorted
+    pub categories: Vec<T>,
+    /// The proportions of each category for each target, in the order of
+    /// `categories`. `None` for targets without matched categories.
+    pub proportions: Vec<Vec<Option<f64>>>,
+}
+
+/// Transfers attributes of source features to target features by the
+/// length they share.
+///
+/// Missing and NaN values are ignored. Targets with no matched values are
+/// `None`.
+#[derive(Debug, Clone)]
+pub struct Aggregator<'a> {
+    source_len: &'a [f64],
+    target_len: &'a [f64],
+    // the matched sources of each target and their shared lengths
+    sources: Vec<Vec<(usize, f64)>>,
+}
+
+impl<'a> Aggregator<'a> {
+    /// Create an aggregator for `table` where `source_len` and `target_len`
+    /// are the lengths of every source and target feature.
+    pub fn new(table: &MatchTable, source_len: &'a [f64], target_len: &'a [f64]) -> Self {
+        let mut sources = vec![Vec::new(); target_len.len()];
+        table
+            .iter()
+            .for_each(|m| sources[m.j].push((m.i, m.shared_len)));
+        Self {
+            source_len,
+            target_len,
+            sources,
+        }
+    }
+
+    /// Number of targets
+    pub fn n_targets(&self) -> usize {
+        self.target_len.len()
+    }
+
+    /// Aggregate a numeric column as chosen by `how`.
+    pub fn numeric(&self, values: &[Option<f64>], how: NumericAggregation) -> Vec<Option<f64>> {
+        match how {
+            NumericAggregation::Extensive => self.extensive(values),
+            NumericAggregation::Intensive => self.intensive(values),
+        }
+    }
+
+    /// Sum of the source values, each scaled by the fraction of the source
+    /// shared with the target.
+    pub fn extensive(&self, values: &[Option<f64>]) -> Vec<Option<f64>> {
+        self.sources
+            .iter()
+            .map(|sources| {
+                sources
+                    .iter()
+                    .filter_map(|&(i, shared_len)| {
+                        let v = values[i].filter(|v| !v.is_nan())?;
+                        let weight = shared_len / self.source_len[i];
+                        weight.is_finite().then_some(v * weight)
+                    })
+                    .fold(None, |total, v| Some(total.unwrap_or(0.0) + v))
+            })
+            .collect()
+    }
+
+    /// Mean of the source values weighted by the fraction of the target
+    /// shared with each source.
+    pub fn intensive(&self, values: &[Option<f64>]) -> Vec<Option<f64>> {
+        (0..self.n_targets())
+            .map(|j| {
+                let (total, weights) = self
+                    .weights(j, values, |v| v.filter(|v| !v.is_nan()))
+                    .fold((0.0, 0.0), |(total, weights), (v, w)| {
+                        (total + v * w, weights + w)
+                    });
+                (weights > 0.0).then(|| total / weights)
+            })
+            .collect()
+    }
+
+    /// Proportion of each category among the sources of each target,
+    /// weighted by the fraction of the target shared with each source.
+    pub fn categorical<T: Ord + Clone>(&self, values: &[Option<T>]) -> CategoryProportions<T> {
+        let mut categories = values.iter().flatten().cloned().collect::<Vec<_>>();
+        categories.sort();
+        categories.dedup();
+
+        let mut proportions = vec![vec![None; self.n_targets()]; categories.len()];
+        (0..self.n_targets()).for_each(|j| {
+            let mut by_category: BTreeMap<usize, f64> = BTreeMap::new();
+            self.weights(j, values, |v| {
+                v.as_ref()
+                    .map(|v| categories.binary_search(v).expect("category is known"))
+            })
+            .for_each(|(c, w)| *by_category.entry(c).or_insert(0.0) += w);
+
+            let total = by_category.values().sum::<f64>();
+            if total > 0.0 {
+                proportions.iter_mut().enumerate().for_each(|(c, p)| {
+                    p[j] = Some(by_category.get(&c).copied().unwrap_or(0.0) / total);
+                });
+            }
+        });
+
+        CategoryProportions {
+            categories,
+            proportions,
+        }
+    }
+
+    // the values of the sources of target `j` with their share of the target
+    fn weights<'v, T, V>(
+        &'v self,
+        j: usize,
+        values: &'v [T],
+        value: impl Fn(&'v T) -> Option<V> + 'v,
+    ) -> impl Iterator<Item = (V, f64)> + 'v {
+        self.sources[j].iter().filter_map(move |&(i, shared_len)| {
+            let v = value(&values[i])?;
+            let weight = shared_len / self.target_len[j];
+            (weight.is_finite() && weight > 0.0).then_some((v, weight))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::Match;
+
+    // source 0 is split between targets 0 and 1, which also matches source
+    // 1. target 2 has no matches.
+    fn table() -> MatchTable {
+        [(0, 0, 60.0), (0, 1, 40.0), (1, 1, 20.0)]
+            .into_iter()
+            .map(|(i, j, shared_len)| Match {
+                i,
+                j,
+                shared_len,
+                n_segments: 1,
+            })
+            .collect()
+    }
+
+    const SOURCE_LEN: [f64; 2] = [100.0, 50.0];
+    const TARGET_LEN: [f64; 3] = [60.0, 80.0, 10.0];
+
+    fn assert_close(res: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(res.len(), expected.len());
+        res.iter().zip(expected).for_each(|(a, b)| match (a, b) {
+            (Some(a), Some(b)) => assert!((a - b).abs() < 1e-9, "{res:?}"),
+            _ => assert_eq!(a, b, "{res:?}"),
+        });
+    }
+
+    #[test]
+    fn extensive_values_are_split_by_the_shared_fraction_of_the_source() {
+        let table = table();
+        let aggregator = Aggregator::new(&table, &SOURCE_LEN, &TARGET_LEN);
+        let res = aggregator.extensive(&[Some(10.0), Some(5.0)]);
+        // 10 * 60 / 100 and 10 * 40 / 100 + 5 * 20 / 50
+        assert_close(&res, &[Some(6.0), Some(6.0), None]);
+        assert_eq!(
+            aggregator.numeric(&[Some(10.0), Some(5.0)], NumericAggregation::Extensive),
+            res
+        );
+    }
+
+    #[test]
+    fn intensive_values_are_weighted_by_the_shared_fraction_of_the_target() {
+        let table = table();
+        let aggregator = Aggregator::new(&table, &SOURCE_LEN, &TARGET_LEN);
+        let res = aggregator.intensive(&[Some(10.0), Some(5.0)]);
+        // (10 * 0.5 + 5 * 0.25) / 0.75
+        assert_close(&res, &[Some(10.0), Some(25.0 / 3.0), None]);
+        assert_eq!(
+            aggregator.numeric(&[Some(10.0), Some(5.0)], NumericAggregation::Intensive),
+            res
+        );
+    }
+
+    #[test]
+    fn categories_are_weighted_by_the_shared_fraction_of_the_target() {
+        let table = table();
+        let aggregator = Aggregator::new(&table, &SOURCE_LEN, &TARGET_LEN);
+        let res = aggregator.categorical(&[Some("a"), Some("b")]);
+        assert_eq!(res.categories, vec!["a", "b"]);
+        assert_close(&res.proportions[0], &[Some(1.0), Some(2.0 / 3.0), None]);
+        assert_close(&res.proportions[1], &[Some(0.0), Some(1.0 / 3.0), None]);
+    }
+
+    #[test]
+    fn missing_values_are_skipped() {
+        let table = table();
+        let aggregator = Aggregator::new(&table, &SOURCE_LEN, &TARGET_LEN);
+        for values in [[Some(f64::NAN), Some(5.0)], [None, Some(5.0)]] {
+            assert_close(&aggregator.extensive(&values), &[None, Some(2.0), None]);
+            assert_close(&aggregator.intensive(&values), &[None, Some(5.0), None]);
+        }
+
+        let res = aggregator.categorical(&[None, Some("b")]);
+        assert_eq!(res.categories, vec!["b"]);
+        assert_close(&res.proportions[0], &[None, Some(1.0), None]);
+    }
+}
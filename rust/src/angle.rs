@@ -0,0 +1,98 @@
+use crate::structs::CrsType;
+use geo_types::Line;
+
+/// How the bearings of two lines are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AngleMode {
+    /// Ignore the direction of the lines. Bearings are compared modulo
+    /// 180 degrees so lines pointing in opposite directions match.
+    Undirected,
+    /// Bearings are compared modulo 360 degrees so lines must point
+    /// in the same direction.
+    Directed,
+}
+
+/// The bearing of a line in degrees clockwise from north in `[0, 360)`.
+///
+/// For a geographic CRS the change in longitude is scaled by the cosine
+/// of the mean latitude so that bearings are not distorted away from the
+/// equator. Returns `NaN` for a zero-length line, which has no bearing.
+pub fn bearing(line: &Line, crs_type: CrsType) -> f64 {
+    let (mut dx, dy) = line.delta().x_y();
+    if dx == 0.0 && dy == 0.0 {
+        return f64::NAN;
+    }
+    if crs_type == CrsType::Geographic {
+        let mid_lat = (line.start.y + line.end.y) / 2.0;
+        dx *= mid_lat.to_radians().cos();
+    }
+    dx.atan2(dy).to_degrees().rem_euclid(360.0)
+}
+
+/// The smallest difference between two bearings in degrees.
+///
+/// In [AngleMode::Undirected] the result is in `[0, 90]`,
+/// in [AngleMode::Directed] it is in `[0, 180]`.
+/// Returns `NaN` if either bearing is `NaN`.
+pub fn angle_difference(a: f64, b: f64, mode: AngleMode) -> f64 {
+    let period = match mode {
+        AngleMode::Undirected => 180.0,
+        AngleMode::Directed => 360.0,
+    };
+    let d = (a - b).rem_euclid(period);
+    d.min(period - d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::coord;
+
+    fn line(x0: f64, y0: f64, x1: f64, y1: f64) -> Line {
+        Line::new(coord! { x: x0, y: y0 }, coord! { x: x1, y: y1 })
+    }
+
+    #[test]
+    fn bearings_are_clockwise_from_north() {
+        assert_eq!(bearing(&line(0., 0., 0., 1.), CrsType::Projected), 0.0);
+        assert_eq!(bearing(&line(0., 0., 1., 0.), CrsType::Projected), 90.0);
+        assert_eq!(bearing(&line(0., 1., 0., 0.), CrsType::Projected), 180.0);
+        assert_eq!(bearing(&line(1., 0., 0., 0.), CrsType::Projected), 270.0);
+    }
+
+    #[test]
+    fn near_vertical_lines_with_opposite_slopes_match_undirected() {
+        // slopes of +1000 and -1000 lean either side of north
+        let a = bearing(&line(0., 0., 1., 1000.), CrsType::Projected);
+        let b = bearing(&line(0., 0., -1., 1000.), CrsType::Projected);
+        let diff = angle_difference(a, b, AngleMode::Undirected);
+        assert!(diff < 0.2, "{diff}");
+
+        // and either side of south when one points down
+        let b = bearing(&line(0., 1000., 1., 0.), CrsType::Projected);
+        let diff = angle_difference(a, b, AngleMode::Undirected);
+        assert!(diff < 0.2, "{diff}");
+    }
+
+    #[test]
+    fn antiparallel_lines_only_match_undirected() {
+        let a = bearing(&line(0., 0., 10., 10.), CrsType::Projected);
+        let b = bearing(&line(10., 10., 0., 0.), CrsType::Projected);
+        assert_eq!(angle_difference(a, b, AngleMode::Undirected), 0.0);
+        assert_eq!(angle_difference(a, b, AngleMode::Directed), 180.0);
+    }
+
+    #[test]
+    fn bearings_wrap_around_north() {
+        assert!((angle_difference(359.0, 1.0, AngleMode::Directed) - 2.0).abs() < 1e-9);
+        assert!((angle_difference(1.0, 359.0, AngleMode::Undirected) - 2.0).abs() < 1e-9);
+        assert!((angle_difference(179.0, 1.0, AngleMode::Undirected) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_length_lines_have_no_bearing() {
+        let a = bearing(&line(1., 1., 1., 1.), CrsType::Projected);
+        assert!(a.is_nan());
+        assert!(angle_difference(a, 0.0, AngleMode::Undirected).is_nan());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::table::{Match, MatchTable};
+use std::collections::BTreeMap;
+
+/// How matched pairs are reduced to partners for each feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assignment {
+    /// Keep every matched pair
+    ManyToMany,
+    /// Each source and target has at most one partner. Pairs are chosen to
+    /// maximise the total shared length.
+    OneToOne,
+    /// Each source keeps only the target it shares the most length with.
+    /// Ties are broken in favour of the lowest target index.
+    ManyToOne,
+    /// Each target keeps only the source it shares the most length with.
+    /// Ties are broken in favour of the lowest source index.
+    OneToMany,
+}
+
+impl MatchTable {
+    /// Reduce the matched pairs according to an [Assignment].
+    pub fn assign(&self, assignment: Assignment) -> MatchTable {
+        match assignment {
+            Assignment::ManyToMany => self.clone(),
+            Assignment::ManyToOne => best_partners(self.iter(), |m| m.i),
+            Assignment::OneToMany => best_partners(self.iter(), |m| m.j),
+            Assignment::OneToOne => optimal_assignment(self),
+        }
+    }
+}
+
+// keep the match with the largest shared length for each key
+fn best_partners(
+    matches: impl Iterator<Item = Match>,
+    key: impl Fn(&Match) -> usize,
+) -> MatchTable {
+    let mut best: BTreeMap<usize, Match> = BTreeMap::new();
+    matches.for_each(|m| {
+        best.entry(key(&m))
+            .and_modify(|b| {
+                if m.shared_len > b.shared_len {
+                    *b = m;
+                }
+            })
+            .or_insert(m);
+    });
+    best.into_values().collect()
+}
+
+// Find the one-to-one assignment with the largest total shared length.
+//
+// Matches form a sparse bipartite graph. Each connected component is
+// solved on its own with the Hungarian algorithm so the dense cost
+// matrices stay small.
+fn optimal_assignment(table: &MatchTable) -> MatchTable {
+    let mut components: BTreeMap<usize, Vec<Match>> = BTreeMap::new();
+    {
+        // union-find over sources and targets, targets offset by the sources
+        let n_sources = table.iter().map(|m| m.i + 1).max().unwrap_or(0);
+        let n_targets = table.iter().map(|m| m.j + 1).max().unwrap_or(0);
+        let mut parent = (0..n_sources + n_targets).collect::<Vec<_>>();
+        table.iter().for_each(|m| {
+            let a = find(&mut parent, m.i);
+            let b = find(&mut parent, n_sources + m.j);
+            parent[a.max(b)] = a.min(b);
+        });
+        table.iter().for_each(|m| {
+            let root = find(&mut parent, m.i);
+            components.entry(root).or_default().push(m);
+        });
+    }
+
+    components
+        .into_values()
+        .flat_map(|matches| assign_component(&matches))
+        .collect()
+}
+
+// root of `a` in a union-find forest, halving paths on the way
+fn find(parent: &mut [usize], mut a: usize) -> usize {
+    while parent[a] != a {
+        parent[a] = parent[parent[a]];
+        a = parent[a];
+    }
+    a
+}
+
+// Solve the assignment for the matches of a single connected component
+fn assign_component(matches: &[Match]) -> Vec<Match> {
+    if let [m] = matches {
+        return vec![*m];
+    }
+
+    let mut sources = matches.iter().map(|m| m.i).collect::<Vec<_>>();
+    let mut targets = matches.iter().map(|m| m.j).collect::<Vec<_>>();
+    sources.sort_unstable();
+    sources.dedup();
+    targets.sort_unstable();
+    targets.dedup();
+
+    // the hungarian algorithm needs no more rows than columns
+    let transpose = sources.len() > targets.len();
+    let (rows, cols) = if transpose {
+        (&targets, &sources)
+    } else {
+        (&sources, &targets)
+    };
+
+    // minimise the negative shared length. unmatched cells cost nothing
+    let mut cost = vec![vec![0.0; cols.len()]; rows.len()];
+    matches.iter().for_each(|m| {
+        let (r, c) = if transpose { (m.j, m.i) } else { (m.i, m.j) };
+        let r = rows.binary_search(&r).unwrap();
+        let c = cols.binary_search(&c).unwrap();
+        cost[r][c] = -m.shared_len;
+    });
+
+    hungarian(&cost)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(r, c)| {
+            let (i, j) = if transpose {
+                (cols[c], rows[r])
+            } else {
+                (rows[r], cols[c])
+            };
+            // rows can be assigned to a column they were not matched with
+            matches.iter().find(|m| m.i == i && m.j == j).copied()
+        })
+        .collect()
+}
+
+// Minimum cost assignment of every row of an n x m matrix with n <= m.
+// Returns the column assigned to each row.
+fn hungarian(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    let m = cost[0].len();
+
+    // potentials and matching use 1-based indices, 0 is a sentinel column
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut row_of = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+
+    for r in 1..=n {
+        row_of[0] = r;
+        let mut c0 = 0;
+        let mut min_to = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[c0] = true;
+            let r0 = row_of[c0];
+            let mut delta = f64::INFINITY;
+            let mut c1 = 0;
+            for c in 1..=m {
+                if used[c] {
+                    continue;
+                }
+                let reduced = cost[r0 - 1][c - 1] - u[r0] - v[c];
+                if reduced < min_to[c] {
+                    min_to[c] = reduced;
+                    way[c] = c0;
+                }
+                if min_to[c] < delta {
+                    delta = min_to[c];
+                    c1 = c;
+                }
+            }
+            for c in 0..=m {
+                if used[c] {
+                    u[row_of[c]] += delta;
+                    v[c] -= delta;
+                } else {
+                    min_to[c] -= delta;
+                }
+            }
+            c0 = c1;
+            if row_of[c0] == 0 {
+                break;
+            }
+        }
+        // augment along the alternating path
+        loop {
+            let c1 = way[c0];
+            row_of[c0] = row_of[c1];
+            c0 = c1;
+            if c0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut col_of = vec![0; n];
+    (1..=m)
+        .filter(|&c| row_of[c] != 0)
+        .for_each(|c| col_of[row_of[c] - 1] = c - 1);
+    col_of
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(matches: &[(usize, usize, f64)]) -> MatchTable {
+        matches
+            .iter()
+            .map(|&(i, j, shared_len)| Match {
+                i,
+                j,
+                shared_len,
+                n_segments: 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hungarian_finds_the_minimum_cost() {
+        let cost = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        assert_eq!(hungarian(&cost), vec![1, 0, 2]);
+
+        // taking the cheapest cell of the first row costs 1 + 100
+        let cost = vec![vec![1.0, 2.0], vec![2.0, 100.0]];
+        assert_eq!(hungarian(&cost), vec![1, 0]);
+    }
+
+    #[test]
+    fn hungarian_with_more_columns_than_rows() {
+        let cost = vec![vec![5.0, 1.0, 9.0, 2.0], vec![4.0, 3.0, 1.0, 7.0]];
+        assert_eq!(hungarian(&cost), vec![1, 2]);
+    }
+
+    #[test]
+    fn one_to_one_maximises_the_total_shared_length() {
+        // the longest match (0, 0) is not part of the best assignment
+        let t = table(&[(0, 0, 10.0), (0, 1, 9.0), (1, 0, 8.0), (2, 5, 3.0)]);
+        let assigned = t.assign(Assignment::OneToOne);
+        assert_eq!(assigned, table(&[(0, 1, 9.0), (1, 0, 8.0), (2, 5, 3.0)]));
+    }
+
+    #[test]
+    fn one_to_one_with_more_sources_than_targets() {
+        let t = table(&[(0, 0, 5.0), (1, 0, 6.0), (2, 0, 2.0), (2, 1, 1.0)]);
+        let assigned = t.assign(Assignment::OneToOne);
+        assert_eq!(assigned, table(&[(1, 0, 6.0), (2, 1, 1.0)]));
+    }
+
+    #[test]
+    fn best_partners_break_ties_by_lowest_index() {
+        let t = table(&[(0, 1, 4.0), (0, 2, 4.0), (1, 1, 4.0), (1, 2, 1.0)]);
+        assert_eq!(
+            t.assign(Assignment::ManyToOne),
+            table(&[(0, 1, 4.0), (1, 1, 4.0)])
+        );
+        assert_eq!(
+            t.assign(Assignment::OneToMany),
+            table(&[(0, 1, 4.0), (0, 2, 4.0)])
+        );
+    }
+}
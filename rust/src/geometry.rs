@@ -0,0 +1,268 @@
+use crate::structs::feature_lines;
+use crate::table::SegmentMatch;
+use geo_types::{Coord, LineString, MultiLineString};
+use std::collections::BTreeMap;
+
+// Fractions closer than this are treated as the same position
+const FRACTION_EPSILON: f64 = 1e-9;
+
+/// A portion of a feature given as the index of a component line and
+/// the start and end of the portion as fractions along that line.
+pub type LinePortion = (usize, f64, f64);
+
+/// The part of a source feature `i` that is shared with a target feature `j`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedGeometry {
+    pub i: usize,
+    pub j: usize,
+    pub geometry: MultiLineString,
+    pub shared_len: f64,
+}
+
+/// Sort portions and merge those that overlap or touch on the same line
+pub fn merge_portions(mut portions: Vec<LinePortion>) -> Vec<LinePortion> {
+    portions.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    let mut merged: Vec<LinePortion> = Vec::with_capacity(portions.len());
+    portions
+        .into_iter()
+        .for_each(|(k, start, end)| match merged.last_mut() {
+            Some(last) if last.0 == k && start <= last.2 + FRACTION_EPSILON => {
+                last.2 = last.2.max(end);
+            }
+            _ => merged.push((k, start, end)),
+        });
+    merged
+}
+
+/// Build the geometry of merged portions of `x`.
+///
+/// Portions that continue from the end of one component line onto the
+/// start of the next are joined into a single `LineString`, unless the
+/// lines are in different parts that do not touch. Portions with no length
+/// are dropped.
+pub fn portions_to_geometry(x: &MultiLineString, portions: &[LinePortion]) -> MultiLineString {
+    let lines = feature_lines(x).collect::<Vec<_>>();
+    let mut parts: Vec<Vec<Coord>> = Vec::new();
+    let mut prev: Option<LinePortion> = None;
+
+    portions
+        .iter()
+        .filter(|(_, start, end)| end - start > FRACTION_EPSILON)
+        .for_each(|&(k, start, end)| {
+            let line = lines[k];
+            let at = |t: f64| line.start + line.delta() * t;
+            let continues = matches!(
+                prev,
+                Some((pk, _, pend)) if pk + 1 == k
+                    && pend >= 1.0 - FRACTION_EPSILON
+                    && start <= FRACTION_EPSILON
+                    && lines[pk].end == line.start
+            );
+            match parts.last_mut() {
+                Some(part) if continues => part.push(at(end)),
+                _ => parts.push(vec![at(start), at(end)]),
+            }
+            prev = Some((k, start, end));
+        });
+
+    MultiLineString::new(parts.into_iter().map(LineString::new).collect())
+}
+
+/// Build the [SharedGeometry] of each matched `(i, j)` pair from its
+/// segment matches. `x` are the source features.
+pub fn shared_geometries(segments: &[SegmentMatch], x: &[MultiLineString]) -> Vec<SharedGeometry> {
+    let mut pairs: BTreeMap<(usize, usize), (Vec<LinePortion>, f64)> = BTreeMap::new();
+    segments.iter().for_each(|s| {
+        let (portions, shared_len) = pairs.entry((s.i, s.j)).or_default();
+        portions.push((
+            s.source_segment,
+            s.overlap.source_start,
+            s.overlap.source_end,
+        ));
+        *shared_len += s.overlap.length;
+    });
+
+    pairs
+        .into_iter()
+        .map(|((i, j), (portions, shared_len))| SharedGeometry {
+            i,
+            j,
+            geometry: portions_to_geometry(&x[i], &merge_portions(portions)),
+            shared_len,
+        })
+        .collect()
+}
+
+/// The part of a feature that is not matched by any feature on the other side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unmatched {
+    /// 0-based position of the feature in its input
+    pub index: usize,
+    pub geometry: MultiLineString,
+    pub unmatched_len: f64,
+    /// Fraction of the length of the feature that is matched
+    pub coverage: f64,
+}
+
+/// Unmatched portions of the source and target features. Every feature has
+/// an entry, fully matched features have an empty geometry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmatchedReport {
+    pub source: Vec<Unmatched>,
+    pub target: Vec<Unmatched>,
+}
+
+/// The portions of `n_lines` component lines not covered by merged `portions`
+pub fn complement_portions(portions: &[LinePortion], n_lines: usize) -> Vec<LinePortion> {
+    let mut gaps = Vec::new();
+    let mut covered = portions.iter().peekable();
+    (0..n_lines).for_each(|k| {
+        let mut pos = 0.0;
+        while let Some(&(_, start, end)) = covered.next_if(|p| p.0 == k) {
+            if start - pos > FRACTION_EPSILON {
+                gaps.push((k, pos, start));
+            }
+            pos = end.max(pos);
+        }
+        if 1.0 - pos > FRACTION_EPSILON {
+            gaps.push((k, pos, 1.0));
+        }
+    });
+    gaps
+}
+
+/// Find the unmatched portions of `lines` given the matched portions of each.
+///
+/// `measures` holds the distance from the start of each feature to the start
+/// of each of its component lines, followed by the total length.
+pub fn unmatched_portions(
+    lines: &[MultiLineString],
+    measures: &[Vec<f64>],
+    matched: Vec<Vec<LinePortion>>,
+) -> Vec<Unmatched> {
+    lines
+        .iter()
+        .zip(measures)
+        .zip(matched)
+        .enumerate()
+        .map(|(index, ((line, offsets), portions))| {
+            let n_lines = offsets.len() - 1;
+            let gaps = complement_portions(&merge_portions(portions), n_lines);
+            let unmatched_len = gaps.iter().fold(0.0, |acc, &(k, start, end)| {
+                acc + (end - start) * (offsets[k + 1] - offsets[k])
+            });
+            let len = offsets[n_lines];
+            let coverage = if len > 0.0 {
+                (1.0 - unmatched_len / len).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            Unmatched {
+                index,
+                geometry: portions_to_geometry(line, &gaps),
+                unmatched_len,
+                coverage,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::line_string;
+
+    #[test]
+    fn overlapping_portions_are_merged() {
+        let portions = vec![
+            (0, 0.5, 0.8),
+            (1, 0.0, 0.2),
+            (0, 0.1, 0.6),
+            (0, 0.8, 0.9),
+            (1, 0.5, 0.7),
+        ];
+        assert_eq!(
+            merge_portions(portions),
+            vec![(0, 0.1, 0.9), (1, 0.0, 0.2), (1, 0.5, 0.7)]
+        );
+    }
+
+    #[test]
+    fn portions_continue_across_vertices() {
+        let x = MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 10., y: 0.), (x: 20., y: 0.), (x: 30., y: 0.)
+        ]]);
+        let geometry = portions_to_geometry(&x, &[(0, 0.5, 1.0), (1, 0.0, 1.0), (2, 0.0, 0.5)]);
+        assert_eq!(
+            geometry,
+            MultiLineString::new(vec![line_string![
+                (x: 5., y: 0.), (x: 10., y: 0.), (x: 20., y: 0.), (x: 25., y: 0.)
+            ]])
+        );
+
+        // a gap within a line starts a new part
+        let geometry = portions_to_geometry(&x, &[(0, 0.0, 0.5), (1, 0.2, 1.0)]);
+        assert_eq!(geometry.0.len(), 2);
+    }
+
+    #[test]
+    fn portions_of_parts_that_do_not_touch_are_separate() {
+        let x = MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.), (x: 10., y: 0.)],
+            line_string![(x: 20., y: 0.), (x: 30., y: 0.)],
+        ]);
+        let geometry = portions_to_geometry(&x, &[(0, 0.5, 1.0), (1, 0.0, 0.5), (1, 0.7, 0.7)]);
+        assert_eq!(
+            geometry,
+            MultiLineString::new(vec![
+                line_string![(x: 5., y: 0.), (x: 10., y: 0.)],
+                line_string![(x: 20., y: 0.), (x: 25., y: 0.)],
+            ])
+        );
+    }
+
+    #[test]
+    fn complement_covers_gaps_between_portions() {
+        let portions = merge_portions(vec![(0, 0.2, 0.4), (0, 0.3, 0.6), (2, 0.0, 1.0)]);
+        assert_eq!(
+            complement_portions(&portions, 3),
+            vec![(0, 0.0, 0.2), (0, 0.6, 1.0), (1, 0.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn fully_matched_feature_has_empty_geometry() {
+        let x = vec![MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 10., y: 0.), (x: 20., y: 0.)
+        ]])];
+        let measures = vec![vec![0.0, 10.0, 20.0]];
+        let matched = vec![vec![(1, 0.0, 1.0), (0, 0.0, 0.7), (0, 0.5, 1.0)]];
+        let unmatched = unmatched_portions(&x, &measures, matched);
+        assert_eq!(
+            unmatched,
+            vec![Unmatched {
+                index: 0,
+                geometry: MultiLineString::new(vec![]),
+                unmatched_len: 0.0,
+                coverage: 1.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn unmatched_portions_join_across_vertices() {
+        let x = vec![MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 10., y: 0.), (x: 20., y: 0.)
+        ]])];
+        let measures = vec![vec![0.0, 10.0, 20.0]];
+        let unmatched = unmatched_portions(&x, &measures, vec![vec![(1, 0.5, 1.0)]]);
+        assert_eq!(
+            unmatched[0].geometry,
+            MultiLineString::new(vec![line_string![
+                (x: 0., y: 0.), (x: 10., y: 0.), (x: 15., y: 0.)
+            ]])
+        );
+        assert_eq!(unmatched[0].unmatched_len, 15.0);
+        assert_eq!(unmatched[0].coverage, 0.25);
+    }
+}
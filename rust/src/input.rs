@@ -0,0 +1,82 @@
+use std::fmt;
+
+/// Per-feature options that do not fit the features they describe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    /// The option named `option` has `found` values for `expected` features
+    FeatureCount {
+        option: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// The option named `option` has `found` elevations for feature
+    /// `feature` which has `expected` coordinates
+    CoordCount {
+        option: &'static str,
+        feature: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A vertical tolerance is set but the option named `option` is not
+    MissingZ { option: &'static str },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FeatureCount {
+                option,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{option}` has {found} values but there are {expected} features"
+            ),
+            Self::CoordCount {
+                option,
+                feature,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{option}` has {found} elevations for feature {feature} which has {expected} coordinates"
+            ),
+            Self::MissingZ { option } => {
+                write!(f, "`vertical_tolerance` is set but `{option}` is not")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Check that there is one of `values` for each of `n` features.
+/// `option` names `values` in the error.
+pub fn check_count<T>(option: &'static str, n: usize, values: &[T]) -> Result<(), InputError> {
+    match values.len() == n {
+        true => Ok(()),
+        false => Err(InputError::FeatureCount {
+            option,
+            expected: n,
+            found: values.len(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_value_for_every_feature() {
+        assert_eq!(check_count("source_layers", 2, &[0, 1]), Ok(()));
+        assert_eq!(
+            check_count("source_layers", 2, &[0]),
+            Err(InputError::FeatureCount {
+                option: "source_layers",
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use crate::input::{check_count, InputError};
+use geo_types::MultiLineString;
+
+/// Range of elevation covered by a component line
+pub type ZRange = (f64, f64);
+
+/// Elevation ranges of the component lines of each feature
+pub type SegmentZRanges = Vec<Vec<ZRange>>;
+
+/// Find the elevation range of every component line of `features`.
+///
+/// `z` holds the elevation of each coordinate of each feature, in the order
+/// of its parts. Panics if a feature does not have one value per coordinate.
+pub fn segment_z_ranges(features: &[MultiLineString], z: &[Vec<f64>]) -> SegmentZRanges {
+    features
+        .iter()
+        .zip(z)
+        .map(|(feature, zi)| {
+            let n_coords = feature.iter().map(|part| part.0.len()).sum::<usize>();
+            assert_eq!(
+                n_coords,
+                zi.len(),
+                "z must have one value per coordinate of each feature"
+            );
+            let mut offset = 0;
+            feature
+                .iter()
+                .flat_map(|part| {
+                    let part_z = &zi[offset..offset + part.0.len()];
+                    offset += part.0.len();
+                    part_z.windows(2).map(|w| (w[0].min(w[1]), w[0].max(w[1])))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Vertical distance between two elevation ranges, 0 if they overlap
+pub fn vertical_gap(a: ZRange, b: ZRange) -> f64 {
+    (a.0 - b.1).max(b.0 - a.1).max(0.0)
+}
+
+/// Check that `z` has one elevation per coordinate of each feature.
+/// `option` names `z` in the error.
+pub fn check_z(
+    option: &'static str,
+    features: &[MultiLineString],
+    z: &[Vec<f64>],
+) -> Result<(), InputError> {
+    check_count(option, features.len(), z)?;
+    features
+        .iter()
+        .zip(z)
+        .enumerate()
+        .try_for_each(|(feature, (f, zi))| {
+            let n_coords = f.iter().map(|part| part.0.len()).sum::<usize>();
+            match n_coords == zi.len() {
+                true => Ok(()),
+                false => Err(InputError::CoordCount {
+                    option,
+                    feature,
+                    expected: n_coords,
+                    found: zi.len(),
+                }),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::line_string;
+
+    fn features() -> Vec<MultiLineString> {
+        vec![
+            MultiLineString::new(vec![line_string![(x: 0., y: 0.), (x: 1., y: 0.)]]),
+            MultiLineString::new(vec![
+                line_string![(x: 0., y: 1.), (x: 1., y: 1.)],
+                line_string![(x: 2., y: 1.), (x: 3., y: 1.), (x: 4., y: 1.)],
+            ]),
+        ]
+    }
+
+    #[test]
+    fn z_fits_every_coordinate() {
+        let z = vec![vec![0.0; 2], vec![0.0; 5]];
+        assert_eq!(check_z("source_z", &features(), &z), Ok(()));
+    }
+
+    #[test]
+    fn z_for_too_few_features() {
+        let z = vec![vec![0.0; 2]];
+        assert_eq!(
+            check_z("source_z", &features(), &z),
+            Err(InputError::FeatureCount {
+                option: "source_z",
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn z_for_too_few_coordinates() {
+        let z = vec![vec![0.0; 2], vec![0.0; 2]];
+        assert_eq!(
+            check_z("target_z", &features(), &z),
+            Err(InputError::CoordCount {
+                option: "target_z",
+                feature: 1,
+                expected: 5,
+                found: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::table::SegmentMatch;
+use std::collections::BTreeMap;
+
+/// The shared portion of a matched pair as linear reference measures.
+///
+/// Measures are distances from the start of each feature, summed across the
+/// parts of a `MultiLineString` in order. Fractions are the same measures
+/// divided by the length of the feature. When a pair shares more than one
+/// portion, the measures span from the start of the first to the end of
+/// the last.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearReference {
+    pub i: usize,
+    pub j: usize,
+    pub source_from: f64,
+    pub source_to: f64,
+    pub source_from_fraction: f64,
+    pub source_to_fraction: f64,
+    pub target_from: f64,
+    pub target_to: f64,
+    pub target_from_fraction: f64,
+    pub target_to_fraction: f64,
+    pub shared_len: f64,
+}
+
+/// Convert segment matches into a [LinearReference] for each `(i, j)` pair.
+///
+/// `x_measures` and `y_measures` hold the distance from the start of each
+/// source and target feature to the start of each of its component lines,
+/// followed by the total length of the feature.
+pub fn linear_references(
+    segments: &[SegmentMatch],
+    x_measures: &[Vec<f64>],
+    y_measures: &[Vec<f64>],
+) -> Vec<LinearReference> {
+    let mut pairs: BTreeMap<(usize, usize), LinearReference> = BTreeMap::new();
+
+    segments.iter().for_each(|s| {
+        let (source_from, source_to) = measures(
+            &x_measures[s.i],
+            s.source_segment,
+            s.overlap.source_start,
+            s.overlap.source_end,
+        );
+        let (target_from, target_to) = measures(
+            &y_measures[s.j],
+            s.target_segment,
+            s.overlap.target_start,
+            s.overlap.target_end,
+        );
+
+        let r = pairs.entry((s.i, s.j)).or_insert(LinearReference {
+            i: s.i,
+            j: s.j,
+            source_from,
+            source_to,
+            source_from_fraction: 0.0,
+            source_to_fraction: 0.0,
+            target_from,
+            target_to,
+            target_from_fraction: 0.0,
+            target_to_fraction: 0.0,
+            shared_len: 0.0,
+        });
+        r.source_from = r.source_from.min(source_from);
+        r.source_to = r.source_to.max(source_to);
+        r.target_from = r.target_from.min(target_from);
+        r.target_to = r.target_to.max(target_to);
+        r.shared_len += s.overlap.length;
+    });
+
+    pairs
+        .into_values()
+        .map(|mut r| {
+            let x_len = *x_measures[r.i].last().unwrap_or(&0.0);
+            let y_len = *y_measures[r.j].last().unwrap_or(&0.0);
+            r.source_from_fraction = fraction(r.source_from, x_len);
+            r.source_to_fraction = fraction(r.source_to, x_len);
+            r.target_from_fraction = fraction(r.target_from, y_len);
+            r.target_to_fraction = fraction(r.target_to, y_len);
+            r
+        })
+        .collect()
+}
+
+// convert fractions along component line `k` into measures along its feature
+fn measures(offsets: &[f64], k: usize, start: f64, end: f64) -> (f64, f64) {
+    let seg_len = offsets[k + 1] - offsets[k];
+    (offsets[k] + start * seg_len, offsets[k] + end * seg_len)
+}
+
+fn fraction(measure: f64, len: f64) -> f64 {
+    if len > 0.0 {
+        measure / len
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlap::SegmentOverlap;
+
+    fn segment(k: usize, source: (f64, f64), l: usize, target: (f64, f64)) -> SegmentMatch {
+        SegmentMatch {
+            i: 0,
+            source_segment: k,
+            j: 0,
+            target_segment: l,
+            overlap: SegmentOverlap {
+                source_start: source.0,
+                source_end: source.1,
+                target_start: target.0,
+                target_end: target.1,
+                length: 0.0,
+            },
+            distance: 0.0,
+            separation: 0.0,
+            angle_diff: 0.0,
+            target_shared_len: 0.0,
+        }
+    }
+
+    // a source of lines of 30 and 20 and a target of one line of 100
+    const X_MEASURES: [f64; 3] = [0.0, 30.0, 50.0];
+    const Y_MEASURES: [f64; 2] = [0.0, 100.0];
+
+    #[test]
+    fn measures_run_along_the_features() {
+        let mut s = segment(1, (0.5, 1.0), 0, (0.2, 0.3));
+        s.overlap.length = 10.0;
+        let refs = linear_references(&[s], &[X_MEASURES.to_vec()], &[Y_MEASURES.to_vec()]);
+        assert_eq!(
+            refs,
+            vec![LinearReference {
+                i: 0,
+                j: 0,
+                source_from: 40.0,
+                source_to: 50.0,
+                source_from_fraction: 0.8,
+                source_to_fraction: 1.0,
+                target_from: 20.0,
+                target_to: 30.0,
+                target_from_fraction: 0.2,
+                target_to_fraction: 0.3,
+                shared_len: 10.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn separate_portions_span_first_to_last() {
+        let mut first = segment(0, (0.0, 0.5), 0, (0.0, 0.15));
+        first.overlap.length = 15.0;
+        let mut last = segment(1, (0.5, 1.0), 0, (0.4, 0.5));
+        last.overlap.length = 10.0;
+        let refs = linear_references(
+            &[last, first],
+            &[X_MEASURES.to_vec()],
+            &[Y_MEASURES.to_vec()],
+        );
+        assert_eq!(refs.len(), 1);
+        let r = refs[0];
+        assert_eq!((r.source_from, r.source_to), (0.0, 50.0));
+        assert_eq!((r.target_from, r.target_to), (0.0, 50.0));
+        assert_eq!((r.target_from_fraction, r.target_to_fraction), (0.0, 0.5));
+        // the shared length is only what the portions share
+        assert_eq!(r.shared_len, 25.0);
+    }
+}
@@ -0,0 +1,1555 @@
+use geo::{EuclideanDistance, EuclideanLength, HaversineLength};
+use geo_types::{Line, MultiLineString, Point};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use crate::angle::{angle_difference, bearing, AngleMode};
+use crate::geometry::{shared_geometries, unmatched_portions, SharedGeometry, UnmatchedReport};
+use crate::input::{check_count, InputError};
+use crate::level::{check_z, segment_z_ranges, vertical_gap, SegmentZRanges};
+use crate::linref::{linear_references, LinearReference};
+use crate::metrics::{match_metrics, MatchMetrics};
+use crate::overlap::*;
+use crate::path::{viterbi_path, MatchedPath, PathCandidate, PathWeights};
+use crate::project::LocalProjection;
+use crate::segmentize::{merge_pieces, segmentize, FeaturePieces};
+use crate::simplify::{
+    expand_spans, simplify_feature, FeatureSpans, Simplification, SimplifyMethod,
+};
+use crate::spherical::{degree_offsets, to_local_metres};
+use crate::structs::{feature_lines, CrsType, TarLine};
+use crate::table::{Match, MatchTable, SegmentMatch};
+use crate::tolerance::FeatureTolerance;
+use crate::topology::{topology_scores, Topology, TopologyScore, TopologyWeights};
+use crate::trees::*;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Number of target features above which [IndexStrategy::Auto]
+/// builds a second R* Tree for the targets.
+pub const AUTO_TWO_TREES_THRESHOLD: usize = 10_000;
+
+/// How candidate pairs between source and target lines are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexStrategy {
+    /// Index only the source lines and query the tree with each
+    /// target component line. Faster with few target features.
+    SourceTree,
+    /// Index both source and target lines and find candidates by
+    /// intersecting the two trees.
+    TwoTrees,
+    /// Pick [IndexStrategy::TwoTrees] when there are more than
+    /// [AUTO_TWO_TREES_THRESHOLD] target features, otherwise
+    /// [IndexStrategy::SourceTree].
+    Auto,
+}
+
+/// Tolerances and settings used by a [Matcher].
+///
+/// Construct with [MatchOptions::new] and adjust with the builder methods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchOptions {
+    /// Distance tolerance used when no per-feature tolerance is set
+    pub distance_tolerance: f64,
+    /// Distance tolerance of each source feature
+    pub source_tolerance: Option<FeatureTolerance>,
+    /// Distance tolerance of each target feature
+    pub target_tolerance: Option<FeatureTolerance>,
+    pub angle_tolerance: f64,
+    pub angle_mode: AngleMode,
+    pub crs_type: CrsType,
+    pub index_strategy: IndexStrategy,
+    /// Number of threads used to evaluate candidates. `None` uses the
+    /// global rayon pool. Only used with the `parallel` feature.
+    pub threads: Option<usize>,
+    /// Smallest accumulated shared length of a matched pair
+    pub min_shared_len: f64,
+    /// Smallest fraction of the source feature a matched pair must cover
+    pub min_source_coverage: f64,
+    /// Smallest fraction of the target feature a matched pair must cover
+    pub min_target_coverage: f64,
+    /// Largest vertical distance between matched lines
+    pub vertical_tolerance: Option<f64>,
+    /// Elevation of each coordinate of each source feature. Shared between
+    /// clones of the options.
+    pub source_z: Option<Arc<[Vec<f64>]>>,
+    /// Elevation of each coordinate of each target feature
+    pub target_z: Option<Arc<[Vec<f64>]>>,
+    /// Layer of each source feature
+    pub source_layers: Option<Arc<[i64]>>,
+    /// Layer of each target feature
+    pub target_layers: Option<Arc<[i64]>>,
+    /// Longest component line used for matching. Longer lines are split
+    /// into equal pieces before indexing.
+    pub max_segment_length: Option<f64>,
+    /// Simplification of the features before matching
+    pub simplify: Option<Simplification>,
+}
+
+impl MatchOptions {
+    /// Create options with the given distance and angle tolerances.
+    /// The angle tolerance is in degrees. Defaults to a projected CRS,
+    /// [AngleMode::Undirected] and [IndexStrategy::Auto] with no minimum
+    /// shared length or coverage.
+    pub fn new(distance_tolerance: f64, angle_tolerance: f64) -> Self {
+        Self {
+            distance_tolerance,
+            source_tolerance: None,
+            target_tolerance: None,
+            angle_tolerance,
+            angle_mode: AngleMode::Undirected,
+            crs_type: CrsType::Projected,
+            index_strategy: IndexStrategy::Auto,
+            threads: None,
+            min_shared_len: 0.0,
+            min_source_coverage: 0.0,
+            min_target_coverage: 0.0,
+            vertical_tolerance: None,
+            source_z: None,
+            target_z: None,
+            source_layers: None,
+            target_layers: None,
+            max_segment_length: None,
+            simplify: None,
+        }
+    }
+
+    /// Use a distance tolerance per source feature, as a `Vec<f64>` or
+    /// [FeatureTolerance::from_fn]. See [MatchOptions::target_tolerance].
+    pub fn source_tolerance(mut self, tolerance: impl Into<FeatureTolerance>) -> Self {
+        self.source_tolerance = Some(tolerance.into());
+        self
+    }
+
+    /// Use a distance tolerance per target feature, as a `Vec<f64>` or
+    /// [FeatureTolerance::from_fn].
+    ///
+    /// A pair of features is matched within the tolerance of whichever
+    /// feature has one. When both have one the larger is used.
+    pub fn target_tolerance(mut self, tolerance: impl Into<FeatureTolerance>) -> Self {
+        self.target_tolerance = Some(tolerance.into());
+        self
+    }
+
+    pub fn angle_mode(mut self, angle_mode: AngleMode) -> Self {
+        self.angle_mode = angle_mode;
+        self
+    }
+
+    pub fn crs_type(mut self, crs_type: CrsType) -> Self {
+        self.crs_type = crs_type;
+        self
+    }
+
+    pub fn index_strategy(mut self, index_strategy: IndexStrategy) -> Self {
+        self.index_strategy = index_strategy;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Drop pairs whose accumulated shared length is less than `min_shared_len`
+    pub fn min_shared_len(mut self, min_shared_len: f64) -> Self {
+        self.min_shared_len = min_shared_len;
+        self
+    }
+
+    /// Drop pairs that cover less than `min_source_coverage` of the length
+    /// of the source feature
+    pub fn min_source_coverage(mut self, min_source_coverage: f64) -> Self {
+        self.min_source_coverage = min_source_coverage;
+        self
+    }
+
+    /// Drop pairs that cover less than `min_target_coverage` of the length
+    /// of the target feature
+    pub fn min_target_coverage(mut self, min_target_coverage: f64) -> Self {
+        self.min_target_coverage = min_target_coverage;
+        self
+    }
+
+    /// Only match lines whose elevations are within `vertical_tolerance`.
+    /// Needs both [MatchOptions::source_z] and [MatchOptions::target_z].
+    pub fn vertical_tolerance(mut self, vertical_tolerance: f64) -> Self {
+        self.vertical_tolerance = Some(vertical_tolerance);
+        self
+    }
+
+    /// Set the elevation of each coordinate of each source feature,
+    /// in the order of its parts
+    pub fn source_z(mut self, z: Vec<Vec<f64>>) -> Self {
+        self.source_z = Some(z.into());
+        self
+    }
+
+    /// Set the elevation of each coordinate of each target feature,
+    /// in the order of its parts
+    pub fn target_z(mut self, z: Vec<Vec<f64>>) -> Self {
+        self.target_z = Some(z.into());
+        self
+    }
+
+    /// Set the layer of each source feature. When target layers are also
+    /// set only features on the same layer are matched.
+    pub fn source_layers(mut self, layers: Vec<i64>) -> Self {
+        self.source_layers = Some(layers.into());
+        self
+    }
+
+    /// Set the layer of each target feature. When source layers are also
+    /// set only features on the same layer are matched.
+    pub fn target_layers(mut self, layers: Vec<i64>) -> Self {
+        self.target_layers = Some(layers.into());
+        self
+    }
+
+    /// Split component lines longer than `max_segment_length` into equal
+    /// pieces before indexing. Results still refer to the original lines.
+    pub fn max_segment_length(mut self, max_segment_length: f64) -> Self {
+        self.max_segment_length = Some(max_segment_length);
+        self
+    }
+
+    /// Simplify features before matching with a tolerance of `factor` times
+    /// the distance tolerance of each feature. Shared lengths and fractions
+    /// still refer to the original lines.
+    pub fn simplify(mut self, method: SimplifyMethod, factor: f64) -> Self {
+        self.simplify = Some(Simplification { method, factor });
+        self
+    }
+
+    /// Create a [Matcher] from these options
+    pub fn build(self) -> Matcher {
+        Matcher::new(self)
+    }
+}
+
+/// Matches the component lines of source features to those of target
+/// features and accumulates the shared length of each pair.
+///
+/// Features are `LineString`s or `MultiLineString`s. All parts of a
+/// `MultiLineString` share its feature index and its component lines are
+/// numbered in order across the parts.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    options: MatchOptions,
+    // elevation ranges of the component lines of the prepared inputs
+    z_ranges: Option<Arc<(SegmentZRanges, SegmentZRanges)>>,
+    // pieces of the component lines of segmentized inputs
+    pieces: Option<(FeaturePieces, FeaturePieces)>,
+    // dedicated thread pool when MatchOptions::threads is set, shared by clones
+    #[cfg(feature = "parallel")]
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl Matcher {
+    /// Create a matcher. With the `parallel` feature and
+    /// [MatchOptions::threads] set, this builds its thread pool.
+    pub fn new(options: MatchOptions) -> Self {
+        #[cfg(feature = "parallel")]
+        let pool = options.threads.map(|n| {
+            Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(n)
+                    .build()
+                    .expect("failed to build thread pool"),
+            )
+        });
+        Self {
+            options,
+            z_ranges: None,
+            pieces: None,
+            #[cfg(feature = "parallel")]
+            pool,
+        }
+    }
+
+    pub fn options(&self) -> &MatchOptions {
+        &self.options
+    }
+
+    /// Find matching lines between `x` (source) and `y` (target).
+    ///
+    /// Returns a [MatchTable] of the shared length of every matched
+    /// `(i, j)` pair.
+    ///
+    /// With the `parallel` feature, candidate pairs are evaluated across
+    /// threads. Results are merged in the same order as the serial path
+    /// so the output is identical.
+    ///
+    /// With [CrsType::AutoProject] both inputs are projected with a
+    /// [LocalProjection] centred on their combined extent and shared
+    /// lengths are in metres.
+    pub fn find_candidates(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> MatchTable {
+        self.find_candidates_with_lengths(x, y).0
+    }
+
+    /// Find matching lines between `x` and `y` along with the length of
+    /// every source and target feature in the units of the shared lengths,
+    /// as needed by an [Aggregator](crate::Aggregator).
+    pub fn find_candidates_with_lengths(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> (MatchTable, Vec<f64>, Vec<f64>) {
+        let (matcher, x, y) = self.prepare(x, y);
+        let table = to_table(matcher.match_segments(&x, &y));
+        let x_lens = x.iter().map(|xi| matcher.length(xi)).collect();
+        let y_lens = y.iter().map(|yi| matcher.length(yi)).collect();
+        (table, x_lens, y_lens)
+    }
+
+    /// Find matching lines between `x` and `y` and describe the quality
+    /// of each matched `(i, j)` pair. See [MatchMetrics].
+    pub fn find_match_metrics(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> Vec<MatchMetrics> {
+        let (matcher, x, y) = self.prepare(x, y);
+        let segments = matcher.match_segments(&x, &y);
+        let x_lens = x.iter().map(|xi| matcher.length(xi)).collect::<Vec<_>>();
+        let y_lens = y.iter().map(|yi| matcher.length(yi)).collect::<Vec<_>>();
+        match_metrics(&segments, &x_lens, &y_lens)
+    }
+
+    /// Find matching lines between `x` and `y` and locate the shared
+    /// portion of each `(i, j)` pair along both features.
+    /// See [LinearReference].
+    pub fn find_linear_references(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> Vec<LinearReference> {
+        let (matcher, x, y) = self.prepare(x, y);
+        let segments = matcher.match_segments(&x, &y);
+        let x_measures = x.iter().map(|xi| matcher.measures(xi)).collect::<Vec<_>>();
+        let y_measures = y.iter().map(|yi| matcher.measures(yi)).collect::<Vec<_>>();
+        linear_references(&segments, &x_measures, &y_measures)
+    }
+
+    /// Find matching lines between `x` and `y` and return the part of each
+    /// source feature that is shared with each target feature.
+    ///
+    /// Geometries are in the coordinates of `x`, also with
+    /// [CrsType::AutoProject]. See [SharedGeometry].
+    pub fn find_shared_geometries(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> Vec<SharedGeometry> {
+        let x = x.map(Into::into).collect::<Vec<MultiLineString>>();
+        let (matcher, xp, yp) = self.prepare(x.iter().cloned(), y);
+        let segments = matcher.match_segments(&xp, &yp);
+        shared_geometries(&segments, &x)
+    }
+
+    /// Match `x` and `y` and report the portions of every source and target
+    /// feature that are not matched by any feature on the other side.
+    ///
+    /// Geometries are in the input coordinates, also with
+    /// [CrsType::AutoProject]. See [UnmatchedReport].
+    pub fn find_unmatched(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> UnmatchedReport {
+        let x = x.map(Into::into).collect::<Vec<MultiLineString>>();
+        let y = y.map(Into::into).collect::<Vec<MultiLineString>>();
+        let (matcher, xp, yp) = self.prepare(x.iter().cloned(), y.iter().cloned());
+        let segments = matcher.match_segments(&xp, &yp);
+
+        let mut x_matched = vec![Vec::new(); x.len()];
+        let mut y_matched = vec![Vec::new(); y.len()];
+        segments.iter().for_each(|s| {
+            x_matched[s.i].push((
+                s.source_segment,
+                s.overlap.source_start,
+                s.overlap.source_end,
+            ));
+            y_matched[s.j].push((
+                s.target_segment,
+                s.overlap.target_start,
+                s.overlap.target_end,
+            ));
+        });
+
+        let x_measures = xp.iter().map(|xi| matcher.measures(xi)).collect::<Vec<_>>();
+        let y_measures = yp.iter().map(|yi| matcher.measures(yi)).collect::<Vec<_>>();
+        UnmatchedReport {
+            source: unmatched_portions(&x, &x_measures, x_matched),
+            target: unmatched_portions(&y, &y_measures, y_matched),
+        }
+    }
+
+    /// Find the matching component lines of `x` and `y`.
+    ///
+    /// Returns every matched pair of lines with the index of its feature and
+    /// of the line within the feature, ordered by source then target.
+    pub fn find_segment_matches(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> Vec<SegmentMatch> {
+        let (matcher, x, y) = self.prepare(x, y);
+        let mut segments = matcher.match_segments(&x, &y);
+        segments.sort_by_key(|s| (s.i, s.source_segment, s.j, s.target_segment));
+        segments
+    }
+
+    /// Find matching lines between `x` and `y` and score each `(i, j)` pair
+    /// by whether the features connected to `i` and `j` also match.
+    ///
+    /// Connectivity is built from shared endpoints of the features of each
+    /// network. See [topology_scores]. Scores can be turned back into a
+    /// [MatchTable] with [TopologyScore::to_match], for example to pick one
+    /// partner per feature with [MatchTable::assign].
+    pub fn find_topology_scores(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+        weights: TopologyWeights,
+    ) -> Vec<TopologyScore> {
+        let (matcher, xp, yp) = self.prepare(x, y);
+        let table = to_table(matcher.match_segments(&xp, &yp));
+        topology_scores(&table, &Topology::new(&xp), &Topology::new(&yp), weights)
+    }
+
+    /// Match each source feature as an ordered trace onto the target network.
+    ///
+    /// Each component line of a source is split wherever a match with a
+    /// target starts or ends. These portions, in order along the source, are
+    /// the observations of a hidden Markov model whose states are the target
+    /// features sharing them. The cost of observing a portion on a target
+    /// grows with the square of their separation relative to half the
+    /// distance tolerance. Moving between
+    /// targets is cheaper when they share an endpoint. See [viterbi_path].
+    ///
+    /// Returns one [MatchedPath] per source feature with the ordered target
+    /// ids and their shared lengths. Sources without matches have no steps.
+    pub fn find_paths(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+        weights: PathWeights,
+    ) -> Vec<MatchedPath> {
+        let (matcher, xp, yp) = self.prepare(x, y);
+        let segments = matcher.match_segments(&xp, &yp);
+
+        // the matches of each source line
+        let mut by_line: BTreeMap<(usize, usize), Vec<&SegmentMatch>> = BTreeMap::new();
+        segments
+            .iter()
+            .for_each(|s| by_line.entry((s.i, s.source_segment)).or_default().push(s));
+
+        let topology = Topology::new(&yp);
+        xp.iter()
+            .enumerate()
+            .map(|(i, xi)| {
+                let observations = (0..feature_lines(xi).count())
+                    .flat_map(|k| {
+                        let matches = by_line.get(&(i, k)).map_or(&[][..], Vec::as_slice);
+                        line_observations(matches, |j| matcher.pair_tolerance(i, j))
+                    })
+                    .collect::<Vec<_>>();
+                MatchedPath {
+                    i,
+                    steps: viterbi_path(&observations, &topology, weights),
+                }
+            })
+            .collect()
+    }
+
+    /// Find duplicated or overlapping features within `x`.
+    ///
+    /// Matches `x` against itself using a single R* Tree. Features are never
+    /// matched to themselves and each unordered pair is reported once as
+    /// `(i, j)` with `i < j`, with the length of `i` shared with `j`.
+    pub fn find_self_matches(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> MatchTable {
+        // the targets are the sources
+        let mut matcher = self.clone();
+        let options = &mut matcher.options;
+        options.target_tolerance = options.source_tolerance.clone();
+        options.target_z = options.source_z.clone();
+        options.target_layers = options.source_layers.clone();
+
+        let x = x.map(Into::into).collect::<Vec<MultiLineString>>();
+        if let Err(e) = matcher.check_inputs(&x, &x) {
+            panic!("{e}");
+        }
+        if let (Some(_), Some(z)) = (
+            matcher.options.vertical_tolerance,
+            &matcher.options.source_z,
+        ) {
+            let z_ranges = segment_z_ranges(&x, z);
+            matcher.z_ranges = Some(Arc::new((z_ranges.clone(), z_ranges)));
+        }
+        let (matcher, x, _) = matcher.project(x, Vec::new());
+
+        to_table(matcher.match_self(&x))
+    }
+
+    /// Check that the per-feature tolerances, elevations and layers set in
+    /// the options have a value for every feature of `x` and `y`, every
+    /// elevation list one value per coordinate, and that both elevations are
+    /// set with a vertical tolerance. Matching panics with this error
+    /// otherwise.
+    pub fn check_inputs(
+        &self,
+        x: &[MultiLineString],
+        y: &[MultiLineString],
+    ) -> Result<(), InputError> {
+        let MatchOptions {
+            vertical_tolerance,
+            source_tolerance,
+            target_tolerance,
+            source_z,
+            target_z,
+            source_layers,
+            target_layers,
+            ..
+        } = &self.options;
+        if let Some(tolerance) = source_tolerance {
+            tolerance.check("source_tolerance", x.len())?;
+        }
+        if let Some(tolerance) = target_tolerance {
+            tolerance.check("target_tolerance", y.len())?;
+        }
+        if vertical_tolerance.is_some() {
+            if source_z.is_none() {
+                return Err(InputError::MissingZ { option: "source_z" });
+            }
+            if target_z.is_none() {
+                return Err(InputError::MissingZ { option: "target_z" });
+            }
+        }
+        if let Some(z) = source_z {
+            check_z("source_z", x, z)?;
+        }
+        if let Some(z) = target_z {
+            check_z("target_z", y, z)?;
+        }
+        if let Some(layers) = source_layers {
+            check_count("source_layers", x.len(), layers)?;
+        }
+        if let Some(layers) = target_layers {
+            check_count("target_layers", y.len(), layers)?;
+        }
+        Ok(())
+    }
+
+    /// Collect the inputs and return the matcher to use on them.
+    ///
+    /// With [CrsType::AutoProject] the inputs are projected and the
+    /// returned matcher uses [CrsType::Projected]. Panics if the per-feature
+    /// options do not fit the inputs, see [Matcher::check_inputs].
+    fn prepare(
+        &self,
+        x: impl Iterator<Item = impl Into<MultiLineString>>,
+        y: impl Iterator<Item = impl Into<MultiLineString>>,
+    ) -> (Matcher, Vec<MultiLineString>, Vec<MultiLineString>) {
+        let x = x.map(Into::into).collect::<Vec<MultiLineString>>();
+        let y = y.map(Into::into).collect::<Vec<MultiLineString>>();
+        if let Err(e) = self.check_inputs(&x, &y) {
+            panic!("{e}");
+        }
+
+        let mut matcher = self.clone();
+        if let (Some(_), Some(x_z), Some(y_z)) = (
+            self.options.vertical_tolerance,
+            &self.options.source_z,
+            &self.options.target_z,
+        ) {
+            let z_ranges = (segment_z_ranges(&x, x_z), segment_z_ranges(&y, y_z));
+            matcher.z_ranges = Some(Arc::new(z_ranges));
+        }
+        matcher.project(x, y)
+    }
+
+    /// Project the inputs with [CrsType::AutoProject] and return the matcher
+    /// to use on them, which uses [CrsType::Projected].
+    fn project(
+        mut self,
+        x: Vec<MultiLineString>,
+        y: Vec<MultiLineString>,
+    ) -> (Matcher, Vec<MultiLineString>, Vec<MultiLineString>) {
+        if self.options.crs_type != CrsType::AutoProject {
+            return (self, x, y);
+        }
+
+        self.options.crs_type = CrsType::Projected;
+        match LocalProjection::from_extent(x.iter().chain(y.iter())) {
+            Some(proj) => (
+                self,
+                x.iter().map(|xi| proj.project(xi)).collect(),
+                y.iter().map(|yi| proj.project(yi)).collect(),
+            ),
+            // there are no coordinates to project
+            None => (self, x, y),
+        }
+    }
+
+    /// Length of a feature in the units of the shared lengths
+    fn length(&self, x: &MultiLineString) -> f64 {
+        match self.options.crs_type {
+            CrsType::Projected | CrsType::AutoProject => x.euclidean_length(),
+            CrsType::Geographic => x.haversine_length(),
+        }
+    }
+
+    /// Length of a component line in the units of the shared lengths
+    fn line_length(&self, x: &Line) -> f64 {
+        match self.options.crs_type {
+            CrsType::Projected | CrsType::AutoProject => x.euclidean_length(),
+            CrsType::Geographic => x.haversine_length(),
+        }
+    }
+
+    /// Distance from the start of `x` to the start of each component line,
+    /// followed by the length of `x`
+    fn measures(&self, x: &MultiLineString) -> Vec<f64> {
+        let mut total = 0.0;
+        std::iter::once(0.0)
+            .chain(feature_lines(x).map(|li| {
+                total += self.line_length(&li);
+                total
+            }))
+            .collect()
+    }
+
+    /// Match the component lines of `x` to those of `y` and drop the
+    /// segment matches of pairs below the minimum shared length or coverage
+    fn match_segments(&self, x: &[MultiLineString], y: &[MultiLineString]) -> Vec<SegmentMatch> {
+        let segments = self.with_simplified(x, y, |m, x, y| {
+            m.with_segmentized(x, y, |m, x, y| m.find_segments(x, y))
+        });
+        self.filter_pairs(segments, x, y)
+    }
+
+    /// Match the component lines of `x` to those of other features in `x`
+    /// where the source comes before the target
+    fn match_self(&self, x: &[MultiLineString]) -> Vec<SegmentMatch> {
+        let segments = self.with_simplified(x, x, |m, x, y| {
+            m.with_segmentized(x, y, |m, x, _| m.find_self_segments(x))
+        });
+        self.filter_pairs(segments, x, x)
+    }
+
+    /// Run `find` on `x` and `y` simplified by [MatchOptions::simplify], if
+    /// set, and map the matches back to the original component lines
+    fn with_simplified(
+        &self,
+        x: &[MultiLineString],
+        y: &[MultiLineString],
+        find: impl FnOnce(&Matcher, &[MultiLineString], &[MultiLineString]) -> Vec<SegmentMatch>,
+    ) -> Vec<SegmentMatch> {
+        let Some(Simplification { method, factor }) =
+            self.options.simplify.filter(|s| s.factor > 0.0)
+        else {
+            return find(self, x, y);
+        };
+
+        let simplify = |features: &[MultiLineString],
+                        tolerance: &Option<FeatureTolerance>|
+         -> (Vec<_>, Vec<_>) {
+            features
+                .iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    let dist = tolerance
+                        .as_ref()
+                        .map_or(self.options.distance_tolerance, |t| t.get(i));
+                    let epsilon = match self.options.crs_type {
+                        // a degree of longitude is the shorter in metres, so the
+                        // tolerance is the smaller offset in degrees of latitude
+                        CrsType::Geographic => {
+                            let lat = f.0.first().and_then(|p| p.0.first()).map_or(0.0, |c| c.y);
+                            degree_offsets(dist * factor, lat).1
+                        }
+                        CrsType::Projected | CrsType::AutoProject => dist * factor,
+                    };
+                    simplify_feature(f, epsilon, method, |l| self.line_length(l))
+                })
+                .unzip()
+        };
+        let (xs, x_spans) = simplify(x, &self.options.source_tolerance);
+        let (ys, y_spans) = simplify(y, &self.options.target_tolerance);
+
+        // elevations of the simplified lines cover those of the original lines
+        let mut matcher = self.clone();
+        if let Some(z_ranges) = &self.z_ranges {
+            let union = |z: &SegmentZRanges, spans: &FeatureSpans| -> SegmentZRanges {
+                spans
+                    .iter()
+                    .zip(z)
+                    .map(|(feature_spans, zi)| {
+                        feature_spans
+                            .iter()
+                            .map(|span| {
+                                zi[span.first..span.first + span.breaks.len() - 1]
+                                    .iter()
+                                    .fold((f64::INFINITY, f64::NEG_INFINITY), |a, b| {
+                                        (a.0.min(b.0), a.1.max(b.1))
+                                    })
+                            })
+                            .collect()
+                    })
+                    .collect()
+            };
+            let (x_z, y_z) = z_ranges.as_ref();
+            matcher.z_ranges = Some(Arc::new((union(x_z, &x_spans), union(y_z, &y_spans))));
+        }
+
+        let segments = find(&matcher, &xs, &ys);
+        let segments = expand_spans(segments, &xs, &ys, &x_spans, &y_spans);
+
+        // the union lets a line pass that is only level with part of a
+        // span, so check the elevations of the original lines again
+        match (self.options.vertical_tolerance, self.z_ranges.as_deref()) {
+            (Some(vertical_tolerance), Some((x_z, y_z))) => segments
+                .into_iter()
+                .filter(|s| {
+                    let gap = vertical_gap(x_z[s.i][s.source_segment], y_z[s.j][s.target_segment]);
+                    gap <= vertical_tolerance
+                })
+                .collect(),
+            _ => segments,
+        }
+    }
+
+    /// Run `find` on `x` and `y` split into pieces no longer than
+    /// [MatchOptions::max_segment_length], if set, and map the matches back
+    /// to the original component lines
+    fn with_segmentized(
+        &self,
+        x: &[MultiLineString],
+        y: &[MultiLineString],
+        find: impl FnOnce(&Matcher, &[MultiLineString], &[MultiLineString]) -> Vec<SegmentMatch>,
+    ) -> Vec<SegmentMatch> {
+        let Some(max_len) = self.options.max_segment_length.filter(|&l| l > 0.0) else {
+            return find(self, x, y);
+        };
+
+        let split = |features: &[MultiLineString]| -> (Vec<_>, Vec<_>) {
+            features
+                .iter()
+                .map(|f| segmentize(f, max_len, |l| self.line_length(l)))
+                .unzip()
+        };
+        let (xd, x_pieces) = split(x);
+        let (yd, y_pieces) = split(y);
+
+        let mut matcher = self.clone();
+        matcher.pieces = Some((x_pieces, y_pieces));
+        let segments = find(&matcher, &xd, &yd);
+        let (x_pieces, y_pieces) = matcher.pieces.as_ref().expect("pieces are set");
+        merge_pieces(segments, x_pieces, y_pieces)
+    }
+
+    /// Find every matching pair of component lines of different features
+    /// in `x` where the source comes before the target
+    fn find_self_segments(&self, x: &[MultiLineString]) -> Vec<SegmentMatch> {
+        let source_tree = create_source_rtree(x.iter().cloned(), self.options.crs_type);
+        let max_source = self
+            .options
+            .source_tolerance
+            .as_ref()
+            .map(|t| t.max(x.len()));
+        self.with_thread_pool(|| self.match_source_tree(&source_tree, x, max_source, |i, j| i < j))
+    }
+
+    /// Drop the segment matches of pairs below the minimum shared length
+    /// or coverage
+    fn filter_pairs(
+        &self,
+        segments: Vec<SegmentMatch>,
+        x: &[MultiLineString],
+        y: &[MultiLineString],
+    ) -> Vec<SegmentMatch> {
+        let MatchOptions {
+            min_shared_len,
+            min_source_coverage,
+            min_target_coverage,
+            ..
+        } = self.options;
+        if min_shared_len <= 0.0 && min_source_coverage <= 0.0 && min_target_coverage <= 0.0 {
+            return segments;
+        }
+
+        // thresholds apply to the totals of each pair, not single segments
+        let x_lens = x.iter().map(|xi| self.length(xi)).collect::<Vec<_>>();
+        let y_lens = y.iter().map(|yi| self.length(yi)).collect::<Vec<_>>();
+        let keep = match_metrics(&segments, &x_lens, &y_lens)
+            .into_iter()
+            .filter(|m| {
+                m.shared_len >= min_shared_len
+                    && m.source_coverage >= min_source_coverage
+                    && m.target_coverage >= min_target_coverage
+            })
+            .map(|m| (m.i, m.j))
+            .collect::<BTreeSet<_>>();
+
+        segments
+            .into_iter()
+            .filter(|s| keep.contains(&(s.i, s.j)))
+            .collect()
+    }
+
+    /// Find every matching pair of component lines of `x` and `y`
+    fn find_segments(&self, x: &[MultiLineString], y: &[MultiLineString]) -> Vec<SegmentMatch> {
+        let strategy = match self.options.index_strategy {
+            IndexStrategy::Auto if y.len() > AUTO_TWO_TREES_THRESHOLD => IndexStrategy::TwoTrees,
+            IndexStrategy::Auto => IndexStrategy::SourceTree,
+            s => s,
+        };
+
+        let source_tree = create_source_rtree(x.iter().cloned(), self.options.crs_type);
+
+        // targets are padded enough to reach the source with the largest tolerance
+        let max_source = self
+            .options
+            .source_tolerance
+            .as_ref()
+            .map(|t| t.max(x.len()));
+
+        match strategy {
+            IndexStrategy::TwoTrees => {
+                let target_tree = create_target_rtree(
+                    y.iter().cloned(),
+                    |j| self.target_padding(j, max_source),
+                    self.options.crs_type,
+                );
+                self.with_thread_pool(|| self.match_two_trees(&source_tree, &target_tree))
+            }
+            _ => self.with_thread_pool(|| {
+                self.match_source_tree(&source_tree, y, max_source, |_, _| true)
+            }),
+        }
+    }
+
+    /// Evaluate the candidates from intersecting the source and target trees
+    fn match_two_trees(
+        &self,
+        source_tree: &SourceTree,
+        target_tree: &TargetTree,
+    ) -> Vec<SegmentMatch> {
+        let candidates = source_tree
+            .intersection_candidates_with_other_tree(target_tree)
+            .collect::<Vec<_>>();
+
+        let evaluate = |(cx, cy): &(&SourceTreeItem, &TargetTreeItem)| {
+            let (i, k, x_bearing) = cx.data;
+            let (j, l, y_bearing) = cy.data;
+            self.evaluate_pair(
+                (i, k, cx.geom(), x_bearing),
+                (j, l, &cy.geom().0, y_bearing),
+            )
+        };
+
+        #[cfg(feature = "parallel")]
+        let matches = candidates.par_iter().filter_map(evaluate).collect();
+        #[cfg(not(feature = "parallel"))]
+        let matches = candidates.iter().filter_map(evaluate).collect();
+        matches
+    }
+
+    /// Query the source tree with every component line of each target.
+    /// Only candidates of sources `i` and targets `j` where `keep(i, j)`
+    /// are evaluated.
+    fn match_source_tree(
+        &self,
+        source_tree: &SourceTree,
+        y: &[MultiLineString],
+        max_source: Option<f64>,
+        keep: impl Fn(usize, usize) -> bool + Sync,
+    ) -> Vec<SegmentMatch> {
+        let keep = &keep;
+        let evaluate = |(j, lns): (usize, &MultiLineString)| {
+            let padding = self.target_padding(j, max_source);
+            feature_lines(lns)
+                .enumerate()
+                .flat_map(move |(l, li)| {
+                    let envelope = TarLine(li, padding, self.options.crs_type).envelope();
+                    let y_bearing = bearing(&li, self.options.crs_type);
+                    source_tree
+                        .locate_in_envelope_intersecting(&envelope)
+                        .filter(move |cx| keep(cx.data.0, j))
+                        .filter_map(move |cx| {
+                            let (i, k, x_bearing) = cx.data;
+                            self.evaluate_pair((i, k, cx.geom(), x_bearing), (j, l, &li, y_bearing))
+                        })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        #[cfg(feature = "parallel")]
+        let matches = y.par_iter().enumerate().flat_map_iter(evaluate).collect();
+        #[cfg(not(feature = "parallel"))]
+        let matches = y.iter().enumerate().flat_map(evaluate).collect();
+        matches
+    }
+
+    /// Distance tolerance of the pair of source `i` and target `j`
+    fn pair_tolerance(&self, i: usize, j: usize) -> f64 {
+        let MatchOptions {
+            source_tolerance,
+            target_tolerance,
+            ..
+        } = &self.options;
+        match (source_tolerance, target_tolerance) {
+            (Some(s), Some(t)) => s.get(i).max(t.get(j)),
+            (Some(s), None) => s.get(i),
+            (None, Some(t)) => t.get(j),
+            (None, None) => self.options.distance_tolerance,
+        }
+    }
+
+    /// Distance by which the envelope of target `j` is padded.
+    /// `max_source` is the largest source tolerance if there are any.
+    fn target_padding(&self, j: usize, max_source: Option<f64>) -> f64 {
+        match (&self.options.target_tolerance, max_source) {
+            (Some(t), Some(m)) => t.get(j).max(m),
+            (Some(t), None) => t.get(j),
+            (None, Some(m)) => m,
+            (None, None) => self.options.distance_tolerance,
+        }
+    }
+
+    /// Run `f` in a dedicated thread pool if [MatchOptions::threads] is set
+    #[cfg(feature = "parallel")]
+    fn with_thread_pool<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn with_thread_pool<R>(&self, f: impl FnOnce() -> R) -> R {
+        f()
+    }
+
+    /// Evaluate a single candidate pair of component lines.
+    ///
+    /// Each side is given as the index of its feature, the index of the
+    /// component line within it, the line and its bearing.
+    ///
+    /// Returns `None` if the lines are not within the angle tolerance,
+    /// are further apart than the distance tolerance or no part of `x`
+    /// is shared with `y`.
+    fn evaluate_pair(
+        &self,
+        (i, k, x, x_bearing): (usize, usize, &Line, f64),
+        (j, l, y, y_bearing): (usize, usize, &Line, f64),
+    ) -> Option<SegmentMatch> {
+        let MatchOptions {
+            angle_tolerance,
+            angle_mode,
+            crs_type,
+            ..
+        } = self.options;
+
+        // features on different layers never match
+        if let (Some(x_layers), Some(y_layers)) =
+            (&self.options.source_layers, &self.options.target_layers)
+        {
+            if x_layers[i] != y_layers[j] {
+                return None;
+            }
+        }
+
+        // lines further apart vertically than the tolerance never match
+        if let (Some(vertical_tolerance), Some((x_z, y_z))) =
+            (self.options.vertical_tolerance, self.z_ranges.as_deref())
+        {
+            // elevations are of the lines before segmentizing
+            let (zk, zl) = match &self.pieces {
+                Some((x_pieces, y_pieces)) => (x_pieces[i][k].0, y_pieces[j][l].0),
+                None => (k, l),
+            };
+            if vertical_gap(x_z[i][zk], y_z[j][zl]) > vertical_tolerance {
+                return None;
+            }
+        }
+
+        // compare bearings. zero-length lines have a NaN bearing and never match
+        let angle_diff = angle_difference(x_bearing, y_bearing, angle_mode);
+        if angle_diff.is_nan() || angle_diff >= angle_tolerance {
+            return None;
+        }
+
+        // calculate the distance from the line segment
+        // if its within our threshold we include it
+        let distance_tolerance = self.pair_tolerance(i, j);
+        let distance = TarLine(*y, distance_tolerance, crs_type).dist_by_crs(x);
+        if distance > distance_tolerance {
+            return None;
+        }
+
+        // measure the overlap in a planar frame. geographic coordinates are
+        // converted to metres around the start of x
+        let (x, y) = match crs_type {
+            CrsType::Projected | CrsType::AutoProject => (*x, *y),
+            CrsType::Geographic => {
+                let to_metres = |l: &Line| {
+                    Line::new(
+                        to_local_metres(l.start, x.start),
+                        to_local_metres(l.end, x.start),
+                    )
+                };
+                (to_metres(x), to_metres(y))
+            }
+        };
+        let overlap = segment_overlap(&x, &y, distance_tolerance)?;
+
+        // distance from the middle of the shared portion of x to y
+        let mid = (overlap.source_start + overlap.source_end) / 2.0;
+        let separation = Point::from(x.start + x.delta() * mid).euclidean_distance(&y);
+
+        Some(SegmentMatch {
+            i,
+            source_segment: k,
+            j,
+            target_segment: l,
+            overlap,
+            distance,
+            separation,
+            angle_diff,
+            target_shared_len: (overlap.target_end - overlap.target_start) * y.euclidean_length(),
+        })
+    }
+}
+
+// the matched pairs of features and their shared lengths
+fn to_table(segments: Vec<SegmentMatch>) -> MatchTable {
+    segments
+        .into_iter()
+        .map(|s| Match {
+            i: s.i,
+            j: s.j,
+            shared_len: s.overlap.length,
+            n_segments: 1,
+        })
+        .collect()
+}
+
+// the observations of one source line for path matching: the portions of
+// the line between the starts and ends of its matches, in order along it,
+// with the targets sharing each portion
+fn line_observations(
+    matches: &[&SegmentMatch],
+    tolerance: impl Fn(usize) -> f64,
+) -> Vec<Vec<PathCandidate>> {
+    let mut cuts = matches
+        .iter()
+        .flat_map(|s| [s.overlap.source_start, s.overlap.source_end])
+        .collect::<Vec<_>>();
+    cuts.sort_by(f64::total_cmp);
+    cuts.dedup();
+
+    cuts.windows(2)
+        .map(|w| {
+            let mid = (w[0] + w[1]) / 2.0;
+            // best separation and shared length of each target over the portion
+            let mut targets: BTreeMap<usize, (f64, f64)> = BTreeMap::new();
+            matches
+                .iter()
+                .filter(|s| s.overlap.source_start < mid && mid < s.overlap.source_end)
+                .for_each(|s| {
+                    let (separation, shared_len) =
+                        targets.entry(s.j).or_insert((f64::INFINITY, 0.0));
+                    let fraction = (w[1] - w[0]) / (s.overlap.source_end - s.overlap.source_start);
+                    *separation = separation.min(s.separation);
+                    *shared_len += s.overlap.length * fraction;
+                });
+            targets
+                .into_iter()
+                .map(|(j, (separation, shared_len))| {
+                    let sigma = tolerance(j) / 2.0;
+                    let cost = match sigma > 0.0 {
+                        true => 0.5 * (separation / sigma).powi(2),
+                        false => 0.0,
+                    };
+                    PathCandidate {
+                        j,
+                        cost,
+                        shared_len,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::{line_string, LineString};
+
+    // shared length of each matched pair
+    fn shared_lens(matcher: &Matcher, x: &[MultiLineString], y: &[MultiLineString]) -> Vec<f64> {
+        matcher
+            .find_candidates(x.iter().cloned(), y.iter().cloned())
+            .iter()
+            .map(|m| m.shared_len)
+            .collect()
+    }
+
+    #[test]
+    fn segmentizing_and_simplifying_keep_the_shared_lengths() {
+        let x = vec![MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 30., y: 0.), (x: 60., y: 0.), (x: 100., y: 0.)
+        ]])];
+        let y = vec![
+            MultiLineString::new(vec![line_string![
+                (x: -10., y: 1.), (x: 25., y: 1.), (x: 45., y: 1.), (x: 70., y: 1.)
+            ]]),
+            MultiLineString::new(vec![line_string![(x: 70., y: 1.), (x: 120., y: 1.)]]),
+        ];
+        // straight lines so that simplifying only removes vertices
+        let options = MatchOptions::new(2.0, 10.0);
+        let plain = shared_lens(&options.clone().build(), &x, &y);
+        assert_eq!(plain.len(), 2);
+        assert!((plain.iter().sum::<f64>() - 100.0).abs() < 1e-6);
+
+        let variants = [
+            options.clone().max_segment_length(7.0),
+            options
+                .clone()
+                .simplify(SimplifyMethod::DouglasPeucker, 0.5),
+            options.clone().simplify(SimplifyMethod::Visvalingam, 0.5),
+            options
+                .max_segment_length(7.0)
+                .simplify(SimplifyMethod::DouglasPeucker, 0.5),
+        ];
+        for options in variants {
+            let lens = shared_lens(&options.build(), &x, &y);
+            assert_eq!(lens.len(), plain.len());
+            lens.iter()
+                .zip(&plain)
+                .for_each(|(a, b)| assert!((a - b).abs() < 1e-6, "{a} != {b}"));
+        }
+    }
+
+    #[test]
+    fn simplifying_keeps_lines_apart_vertically() {
+        // y climbs away from x after its first line
+        let x = vec![MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 50., y: 0.), (x: 100., y: 0.)
+        ]])];
+        let y = vec![MultiLineString::new(vec![line_string![
+            (x: 0., y: 1.), (x: 50., y: 1.), (x: 100., y: 1.)
+        ]])];
+        let matcher = MatchOptions::new(2.0, 10.0)
+            .vertical_tolerance(2.0)
+            .source_z(vec![vec![0.0, 0.0, 0.0]])
+            .target_z(vec![vec![0.0, 10.0, 10.0]])
+            .simplify(SimplifyMethod::DouglasPeucker, 0.5)
+            .build();
+        let segments = matcher.find_segment_matches(x.into_iter(), y.into_iter());
+        let pairs = segments
+            .iter()
+            .map(|s| (s.source_segment, s.target_segment))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 0)]);
+        assert!((segments[0].overlap.length - 50.0).abs() < 1e-6);
+    }
+
+    // the steps of the path of the only source as (j, shared_len)
+    fn path_steps(x: MultiLineString, y: &[MultiLineString]) -> Vec<(usize, f64)> {
+        let paths = MatchOptions::new(2.0, 10.0).build().find_paths(
+            std::iter::once(x),
+            y.iter().cloned(),
+            PathWeights::default(),
+        );
+        paths[0].steps.iter().map(|s| (s.j, s.shared_len)).collect()
+    }
+
+    fn assert_steps(steps: &[(usize, f64)], expected: &[(usize, f64)]) {
+        assert_eq!(steps.len(), expected.len(), "{steps:?}");
+        steps.iter().zip(expected).for_each(|(a, b)| {
+            assert_eq!(a.0, b.0, "{steps:?}");
+            assert!((a.1 - b.1).abs() < 1e-6, "{steps:?}");
+        });
+    }
+
+    #[test]
+    fn paths_follow_every_edge_along_a_long_line() {
+        let y = [0., 100., 200.].map(|x0| {
+            MultiLineString::new(vec![line_string![(x: x0, y: 1.), (x: x0 + 100., y: 1.)]])
+        });
+        let expected = [(0, 100.0), (1, 100.0), (2, 100.0)];
+
+        let x = MultiLineString::new(vec![line_string![(x: 0., y: 0.), (x: 300., y: 0.)]]);
+        assert_steps(&path_steps(x, &y), &expected);
+
+        let x = MultiLineString::new(vec![line_string![(x: 300., y: 0.), (x: 0., y: 0.)]]);
+        let reversed = expected.iter().rev().copied().collect::<Vec<_>>();
+        assert_steps(&path_steps(x, &y), &reversed);
+
+        let x = MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 50., y: 0.), (x: 150., y: 0.), (x: 250., y: 0.), (x: 300., y: 0.)
+        ]]);
+        assert_steps(&path_steps(x, &y), &expected);
+    }
+
+    #[test]
+    fn per_target_tolerances_widen_the_corridor() {
+        let x = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.), (x: 100., y: 0.)],
+        ])];
+        let y = vec![
+            MultiLineString::new(vec![line_string![(x: 0., y: 3.), (x: 100., y: 3.)]]),
+            MultiLineString::new(vec![line_string![(x: 0., y: -3.), (x: 100., y: -3.)]]),
+        ];
+        let matcher = MatchOptions::new(1.0, 10.0)
+            .target_tolerance(vec![5.0, 1.0])
+            .build();
+        let table = matcher.find_candidates(x.into_iter(), y.into_iter());
+        let pairs = table.iter().map(|m| (m.i, m.j)).collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn tolerances_need_a_value_for_every_feature() {
+        let x = vec![
+            MultiLineString::new(vec![line_string![(x: 0., y: 0.), (x: 100., y: 0.)]]),
+            MultiLineString::new(vec![line_string![(x: 0., y: 5.), (x: 100., y: 5.)]]),
+        ];
+        let matcher = MatchOptions::new(1.0, 10.0)
+            .source_tolerance(vec![1.0])
+            .build();
+        assert_eq!(
+            matcher.check_inputs(&x, &[]),
+            Err(InputError::FeatureCount {
+                option: "source_tolerance",
+                expected: 2,
+                found: 1
+            })
+        );
+
+        let matcher = MatchOptions::new(1.0, 10.0)
+            .source_tolerance(FeatureTolerance::from_fn(|_| 1.0))
+            .build();
+        assert_eq!(matcher.check_inputs(&x, &[]), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "`source_tolerance` has 1 values but there are 2 features")]
+    fn matching_with_too_few_tolerances_panics() {
+        let x = vec![
+            MultiLineString::new(vec![line_string![(x: 0., y: 0.), (x: 100., y: 0.)]]),
+            MultiLineString::new(vec![line_string![(x: 0., y: 5.), (x: 100., y: 5.)]]),
+        ];
+        let matcher = MatchOptions::new(1.0, 10.0)
+            .source_tolerance(vec![1.0])
+            .build();
+        matcher.find_candidates(x.clone().into_iter(), x.into_iter());
+    }
+
+    #[test]
+    fn pairs_count_their_matched_lines() {
+        let x = vec![MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 50., y: 0.), (x: 100., y: 0.)
+        ]])];
+        let y = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 1.), (x: 100., y: 1.)],
+        ])];
+        let table = MatchOptions::new(2.0, 10.0)
+            .build()
+            .find_candidates(x.into_iter(), y.into_iter());
+        let m = table.iter().next().unwrap();
+        assert_eq!(m.n_segments, 2);
+        assert!((m.shared_len - 100.0).abs() < 1e-9);
+    }
+
+    // 0 and 1 are duplicates, 2 overlaps the second half of both and 3 is
+    // on its own
+    fn self_match_fixture() -> Vec<MultiLineString> {
+        vec![
+            MultiLineString::new(vec![line_string![(x: 0., y: 0.), (x: 100., y: 0.)]]),
+            MultiLineString::new(vec![line_string![(x: 0., y: 0.), (x: 100., y: 0.)]]),
+            MultiLineString::new(vec![line_string![(x: 50., y: 1.), (x: 150., y: 1.)]]),
+            MultiLineString::new(vec![line_string![(x: 0., y: 50.), (x: 100., y: 50.)]]),
+        ]
+    }
+
+    fn self_pairs(options: MatchOptions) -> Vec<(usize, usize, f64)> {
+        options
+            .build()
+            .find_self_matches(self_match_fixture().into_iter())
+            .iter()
+            .map(|m| (m.i, m.j, m.shared_len))
+            .collect()
+    }
+
+    #[test]
+    fn self_matches_report_each_pair_once() {
+        let pairs = self_pairs(MatchOptions::new(2.0, 10.0));
+        let ids = pairs.iter().map(|&(i, j, _)| (i, j)).collect::<Vec<_>>();
+        assert_eq!(ids, vec![(0, 1), (0, 2), (1, 2)]);
+        let lens = pairs.iter().map(|p| p.2).collect::<Vec<_>>();
+        lens.iter()
+            .zip([100.0, 50.0, 50.0])
+            .for_each(|(a, b)| assert!((a - b).abs() < 1e-9, "{lens:?}"));
+    }
+
+    #[test]
+    fn self_matches_honour_layers_and_elevations() {
+        let pairs = self_pairs(MatchOptions::new(2.0, 10.0).source_layers(vec![0, 0, 1, 0]));
+        let ids = pairs.iter().map(|&(i, j, _)| (i, j)).collect::<Vec<_>>();
+        assert_eq!(ids, vec![(0, 1)]);
+
+        let z = [0.0, 10.0, 0.0, 0.0].map(|z| vec![z; 2]).to_vec();
+        let pairs = self_pairs(
+            MatchOptions::new(2.0, 10.0)
+                .vertical_tolerance(2.0)
+                .source_z(z),
+        );
+        let ids = pairs.iter().map(|&(i, j, _)| (i, j)).collect::<Vec<_>>();
+        assert_eq!(ids, vec![(0, 2)]);
+    }
+
+    #[test]
+    fn simplifying_skips_single_coordinate_parts() {
+        let x = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.)],
+            line_string![(x: 0., y: 0.), (x: 50., y: 0.), (x: 100., y: 0.)],
+        ])];
+        let y = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 1.), (x: 100., y: 1.)],
+        ])];
+        let matcher = MatchOptions::new(2.0, 10.0)
+            .simplify(SimplifyMethod::DouglasPeucker, 0.5)
+            .build();
+        let report = matcher.find_unmatched(x.into_iter(), y.into_iter());
+        assert!(report.source[0].unmatched_len < 1e-9);
+        assert!(report.target[0].unmatched_len < 1e-9);
+    }
+
+    #[test]
+    fn vertical_tolerance_needs_both_elevations() {
+        let x = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.), (x: 100., y: 0.)],
+        ])];
+        let options = MatchOptions::new(2.0, 10.0)
+            .vertical_tolerance(2.0)
+            .source_z(vec![vec![0.0, 0.0]]);
+        assert_eq!(
+            options.clone().build().check_inputs(&x, &x),
+            Err(InputError::MissingZ { option: "target_z" })
+        );
+
+        let options = options.target_z(vec![vec![0.0, 0.0]]);
+        assert_eq!(options.clone().build().check_inputs(&x, &x), Ok(()));
+
+        // clones share the elevations
+        let matcher = options.build();
+        let copy = matcher.clone();
+        assert!(Arc::ptr_eq(
+            matcher.options.source_z.as_ref().unwrap(),
+            copy.options.source_z.as_ref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn parts_of_a_feature_share_its_index() {
+        // two lines in the first part, a gap, and one line in the second
+        let x = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.), (x: 40., y: 0.), (x: 50., y: 0.)],
+            line_string![(x: 70., y: 0.), (x: 100., y: 0.)],
+        ])];
+        let y = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 1.), (x: 100., y: 1.)],
+        ])];
+        let matcher = MatchOptions::new(2.0, 10.0).build();
+
+        let segments = matcher.find_segment_matches(x.clone().into_iter(), y.clone().into_iter());
+        let lines = segments
+            .iter()
+            .map(|s| (s.i, s.source_segment, s.j, s.target_segment))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![(0, 0, 0, 0), (0, 1, 0, 0), (0, 2, 0, 0)]);
+
+        let table = matcher.find_candidates(x.into_iter(), y.into_iter());
+        let m = table.iter().next().unwrap();
+        assert_eq!((m.i, m.j, m.n_segments), (0, 0, 3));
+        assert!((m.shared_len - 80.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thresholds_apply_to_pair_totals() {
+        // ten lines of 10 along the source
+        let x = vec![MultiLineString::new(vec![LineString::new(
+            (0..=10).map(|k| (k as f64 * 10.0, 0.0).into()).collect(),
+        )])];
+        let y = vec![
+            // runs along the whole source
+            MultiLineString::new(vec![line_string![(x: 0., y: 1.), (x: 100., y: 1.)]]),
+            // crosses the source at a shallow angle
+            MultiLineString::new(vec![line_string![(x: 0., y: -5.), (x: 100., y: 5.)]]),
+            // overlaps the end of the source
+            MultiLineString::new(vec![line_string![(x: 95., y: 1.5), (x: 130., y: 1.5)]]),
+        ];
+        let pairs = |options: MatchOptions| {
+            options
+                .build()
+                .find_candidates(x.clone().into_iter(), y.clone().into_iter())
+                .iter()
+                .map(|m| m.j)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(pairs(MatchOptions::new(2.0, 10.0)), vec![0, 1, 2]);
+        // every single line shares less than 20
+        assert_eq!(
+            pairs(MatchOptions::new(2.0, 10.0).min_shared_len(20.0)),
+            vec![0, 1]
+        );
+        assert_eq!(
+            pairs(MatchOptions::new(2.0, 10.0).min_shared_len(50.0)),
+            vec![0]
+        );
+        assert_eq!(
+            pairs(MatchOptions::new(2.0, 10.0).min_source_coverage(0.5)),
+            vec![0]
+        );
+        assert_eq!(
+            pairs(MatchOptions::new(2.0, 10.0).min_target_coverage(0.5)),
+            vec![0]
+        );
+        assert_eq!(
+            pairs(MatchOptions::new(2.0, 10.0).min_target_coverage(0.1)),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn metrics_of_a_parallel_offset() {
+        let x = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.), (x: 100., y: 0.)],
+        ])];
+        let y = vec![MultiLineString::new(vec![
+            line_string![(x: 20., y: 1.), (x: 70., y: 1.)],
+        ])];
+        let metrics = MatchOptions::new(2.0, 10.0)
+            .build()
+            .find_match_metrics(x.into_iter(), y.into_iter());
+        assert_eq!(metrics.len(), 1);
+        let m = metrics[0];
+        assert!((m.shared_len - 50.0).abs() < 1e-9);
+        assert!((m.min_distance - 1.0).abs() < 1e-9);
+        assert!((m.mean_distance - 1.0).abs() < 1e-9);
+        assert_eq!(m.mean_angle_diff, 0.0);
+        assert!((m.source_coverage - 0.5).abs() < 1e-9);
+        assert!((m.target_coverage - 1.0).abs() < 1e-9);
+        assert_eq!(m.n_segments, 1);
+    }
+
+    #[test]
+    fn linear_references_of_a_parallel_offset() {
+        let x = vec![MultiLineString::new(vec![
+            line_string![(x: 0., y: 0.), (x: 100., y: 0.)],
+        ])];
+        let y = vec![MultiLineString::new(vec![
+            line_string![(x: 70., y: 1.), (x: 20., y: 1.)],
+        ])];
+        let refs = MatchOptions::new(2.0, 10.0)
+            .build()
+            .find_linear_references(x.into_iter(), y.into_iter());
+        assert_eq!(refs.len(), 1);
+        let r = refs[0];
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(r.source_from, 20.0) && close(r.source_to, 70.0));
+        assert!(close(r.source_from_fraction, 0.2) && close(r.source_to_fraction, 0.7));
+        assert!(close(r.target_from, 0.0) && close(r.target_to, 50.0));
+        assert!(close(r.target_from_fraction, 0.0) && close(r.target_to_fraction, 1.0));
+        assert!(close(r.shared_len, 50.0));
+    }
+
+    #[test]
+    fn projected_and_spherical_lengths_agree() {
+        // about 700 m of a street in Leeds and a parallel offset 5 m north
+        let x = vec![MultiLineString::new(vec![line_string![
+            (x: -1.550, y: 53.800), (x: -1.545, y: 53.8005), (x: -1.5395, y: 53.801)
+        ]])];
+        let y = vec![MultiLineString::new(vec![line_string![
+            (x: -1.550, y: 53.80005), (x: -1.545, y: 53.80055), (x: -1.5395, y: 53.80105)
+        ]])];
+        let geographic = MatchOptions::new(10.0, 10.0)
+            .crs_type(CrsType::Geographic)
+            .build();
+        let projected = MatchOptions::new(10.0, 10.0)
+            .crs_type(CrsType::AutoProject)
+            .build();
+        let spherical = shared_lens(&geographic, &x, &y);
+        let ellipsoidal = shared_lens(&projected, &x, &y);
+        assert_eq!(spherical.len(), 1);
+        assert_eq!(ellipsoidal.len(), 1);
+        assert!((spherical[0] - 698.5).abs() < 2.0, "{spherical:?}");
+        // the sphere and the WGS84 ellipsoid differ by a fraction of a percent
+        let diff = (spherical[0] - ellipsoidal[0]).abs() / spherical[0];
+        assert!(diff < 0.005, "{spherical:?} {ellipsoidal:?}");
+    }
+
+    // short random lines in a 1 km square from a fixed seed
+    #[cfg(feature = "parallel")]
+    fn random_lines(n: usize, seed: u64) -> Vec<MultiLineString> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..n)
+            .map(|_| {
+                let (x0, y0) = (next() * 1000.0, next() * 1000.0);
+                let (dx, dy) = (next() * 100.0 - 50.0, next() * 100.0 - 50.0);
+                MultiLineString::new(vec![line_string![
+                    (x: x0, y: y0),
+                    (x: x0 + dx, y: y0 + dy),
+                    (x: x0 + dx + next() * 20.0, y: y0 + dy + next() * 20.0)
+                ]])
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_and_serial_results_are_identical() {
+        let x = random_lines(500, 1);
+        let y = random_lines(500, 2);
+        let find = |options: MatchOptions| {
+            let matcher = options.build();
+            (
+                matcher.find_segment_matches(x.iter().cloned(), y.iter().cloned()),
+                matcher.find_candidates(x.iter().cloned(), y.iter().cloned()),
+                matcher.find_self_matches(x.iter().cloned()),
+            )
+        };
+        let serial = find(MatchOptions::new(5.0, 20.0).threads(1));
+        assert!(!serial.0.is_empty());
+        assert_eq!(find(MatchOptions::new(5.0, 20.0).threads(4)), serial);
+        assert_eq!(find(MatchOptions::new(5.0, 20.0)), serial);
+        assert_eq!(
+            find(
+                MatchOptions::new(5.0, 20.0)
+                    .threads(4)
+                    .index_strategy(IndexStrategy::TwoTrees)
+            ),
+            serial
+        );
+    }
+}
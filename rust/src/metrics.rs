@@ -0,0 +1,158 @@
+use crate::table::SegmentMatch;
+use std::collections::BTreeMap;
+
+/// Describes how well a source feature `i` matches a target feature `j`.
+///
+/// Distances and angles are summarised over all of the matched component
+/// line pairs of `i` and `j`. Means are weighted by shared length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchMetrics {
+    pub i: usize,
+    pub j: usize,
+    pub shared_len: f64,
+    /// Smallest distance between any matched pair of component lines
+    pub min_distance: f64,
+    /// Mean distance from the shared portion of `i` to `j`
+    pub mean_distance: f64,
+    /// Mean difference in bearing in degrees
+    pub mean_angle_diff: f64,
+    /// Fraction of the length of `i` that is shared with `j`
+    pub source_coverage: f64,
+    /// Fraction of the length of `j` that is shared with `i`
+    pub target_coverage: f64,
+    /// Number of matched component line pairs
+    pub n_segments: usize,
+}
+
+/// Summarise segment matches into [MatchMetrics] for each `(i, j)` pair.
+///
+/// `x_lens` and `y_lens` are the lengths of the source and target features.
+/// Coverage fractions are capped at 1 as component lines of one feature can
+/// share the same portion of the other near vertices.
+pub fn match_metrics(
+    segments: &[SegmentMatch],
+    x_lens: &[f64],
+    y_lens: &[f64],
+) -> Vec<MatchMetrics> {
+    let mut pairs: BTreeMap<(usize, usize), MatchMetrics> = BTreeMap::new();
+
+    segments.iter().for_each(|s| {
+        let m = pairs.entry((s.i, s.j)).or_insert(MatchMetrics {
+            i: s.i,
+            j: s.j,
+            shared_len: 0.0,
+            min_distance: f64::INFINITY,
+            mean_distance: 0.0,
+            mean_angle_diff: 0.0,
+            source_coverage: 0.0,
+            target_coverage: 0.0,
+            n_segments: 0,
+        });
+        let len = s.overlap.length;
+        m.shared_len += len;
+        m.min_distance = m.min_distance.min(s.distance);
+        // accumulate weighted sums, divided by the shared length below
+        m.mean_distance += s.separation * len;
+        m.mean_angle_diff += s.angle_diff * len;
+        m.target_coverage += s.target_shared_len;
+        m.n_segments += 1;
+    });
+
+    pairs
+        .into_values()
+        .map(|mut m| {
+            if m.shared_len > 0.0 {
+                m.mean_distance /= m.shared_len;
+                m.mean_angle_diff /= m.shared_len;
+            } else {
+                m.mean_distance = f64::NAN;
+                m.mean_angle_diff = f64::NAN;
+            }
+            m.source_coverage = coverage(m.shared_len, x_lens[m.i]);
+            m.target_coverage = coverage(m.target_coverage, y_lens[m.j]);
+            m
+        })
+        .collect()
+}
+
+fn coverage(shared_len: f64, len: f64) -> f64 {
+    if len > 0.0 {
+        (shared_len / len).min(1.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlap::SegmentOverlap;
+
+    fn segment(
+        (i, j): (usize, usize),
+        length: f64,
+        distance: f64,
+        separation: f64,
+        angle_diff: f64,
+    ) -> SegmentMatch {
+        SegmentMatch {
+            i,
+            source_segment: 0,
+            j,
+            target_segment: 0,
+            overlap: SegmentOverlap {
+                source_start: 0.0,
+                source_end: 1.0,
+                target_start: 0.0,
+                target_end: 1.0,
+                length,
+            },
+            distance,
+            separation,
+            angle_diff,
+            target_shared_len: length,
+        }
+    }
+
+    #[test]
+    fn metrics_are_weighted_by_shared_length() {
+        let segments = [
+            segment((0, 0), 30.0, 1.0, 1.0, 0.0),
+            segment((0, 0), 10.0, 2.0, 3.0, 4.0),
+        ];
+        let metrics = match_metrics(&segments, &[50.0], &[40.0]);
+        assert_eq!(
+            metrics,
+            vec![MatchMetrics {
+                i: 0,
+                j: 0,
+                shared_len: 40.0,
+                min_distance: 1.0,
+                mean_distance: 1.5,
+                mean_angle_diff: 1.0,
+                source_coverage: 0.8,
+                target_coverage: 1.0,
+                n_segments: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn coverage_is_capped_and_pairs_are_separate() {
+        let segments = [
+            segment((0, 1), 30.0, 1.0, 1.0, 0.0),
+            segment((0, 1), 30.0, 1.0, 1.0, 0.0),
+            segment((1, 0), 0.0, 2.0, 2.0, 0.0),
+        ];
+        let metrics = match_metrics(&segments, &[50.0, 10.0], &[0.0, 40.0]);
+        assert_eq!((metrics[0].i, metrics[0].j), (0, 1));
+        assert_eq!(metrics[0].source_coverage, 1.0);
+        assert_eq!(metrics[0].target_coverage, 1.0);
+
+        // a pair sharing no length has no means and no target coverage
+        assert_eq!((metrics[1].i, metrics[1].j), (1, 0));
+        assert!(metrics[1].mean_distance.is_nan());
+        assert!(metrics[1].mean_angle_diff.is_nan());
+        assert_eq!(metrics[1].target_coverage, 0.0);
+    }
+}
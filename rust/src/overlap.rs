@@ -1,19 +1,20 @@
-use geo_types::{Line, Point, Rect};
+use geo_types::{Coord, Line};
 use std::ops::Range;
 
-// TODO for handling geographic CRS
-// calculate the distance from the top left to the bottom left corners
-pub fn x_range(rect: &Rect) -> Range<f64> {
-    rect.min().x..rect.max().x
+/// The portion of a source segment that is shared with a target segment.
+///
+/// The start and end of the shared portion are given as fractions along
+/// each segment, from its start point (0) to its end point (1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentOverlap {
+    pub source_start: f64,
+    pub source_end: f64,
+    pub target_start: f64,
+    pub target_end: f64,
+    /// Length of the shared portion of the source segment
+    pub length: f64,
 }
 
-
-
-// TODO for handling geographic CRS
-// calculate the distance from the top left to the top right corners
-pub fn y_range(rect: &Rect) -> Range<f64> {
-    rect.min().y..rect.max().y
-}
 pub fn overlap_range(r1: Range<f64>, r2: Range<f64>) -> Option<Range<f64>> {
     if r1.end < r2.start || r2.end < r1.start {
         None
@@ -22,112 +23,151 @@ pub fn overlap_range(r1: Range<f64>, r2: Range<f64>) -> Option<Range<f64>> {
     }
 }
 
-// Given the overlap in the domain and range
-// we can calculate the segment lenth of the line that is provided
-// we use the bounding box as a &Rect to determine the width or height
-// of the triangle
-// TODO: solve_segment_length does not handle geographic CRS yet 
-// TO support geographic CRS overlap_range() needs to be able to calculate haversine distance for the overlap
-// this will probably need to be handle by x_range and y_range?
-pub fn solve_segment_length(
-    x_overlap: Option<Range<f64>>,
-    y_overlap: Option<Range<f64>>,
-    bbox: &Rect,
-) -> f64 {
-    if x_overlap.is_some() && y_overlap.is_some() {
-        let (base_w, base_h) = wh(&bbox);
-        let dy = solve_dy(y_overlap.unwrap());
-        let dx = solve_dx(dy, base_w, base_h);
-        solve_h(dx, dy)
-    } else if x_overlap.is_some() {
-        let x_over = x_overlap.unwrap();
-        x_over.end - x_over.start
-    } else if y_overlap.is_some() {
-        let y_over = y_overlap.unwrap();
-        y_over.end - y_over.start
-    } else {
-        unreachable!() // this should never happen
-    }
+// shared portions of a smaller fraction of the source are rounding error
+const MIN_SHARED_FRACTION: f64 = 1e-12;
+
+fn dot(a: Coord, b: Coord) -> f64 {
+    a.x * b.x + a.y * b.y
 }
 
+fn cross(a: Coord, b: Coord) -> f64 {
+    a.x * b.y - a.y * b.x
+}
 
-// When x range is known but y range is not, we need to solve for start and end points 
-// of the line segment
-pub fn solve_no_y_overlap(x_overlap: Range<f64>, x: &Line, slope: &f64) -> (Point, Point) {
-    let (known_x, known_y) = x.points().0.x_y();
-    let b = known_y - (slope * known_x); // Corrected calculation of b
+// Find the part of the target `y` that lies within `tolerance` of the
+// infinite line through `x`. The perpendicular offset of `y` from that line
+// changes linearly along `y` so the part is a single interval.
+fn corridor_range(x: &Line, y: &Line, tolerance: f64) -> Option<Range<f64>> {
+    let dir = x.delta();
+    let len = dot(dir, dir).sqrt();
 
-    let y1 = (slope * x_overlap.start) + b;
-    let y2 = (slope * x_overlap.end) + b;
-    let p1 = Point::new(x_overlap.start, y1);
-    let p2 = Point::new(x_overlap.end, y2);
-    (p1, p2)  
-}
+    // signed perpendicular offsets of the target endpoints
+    let h0 = cross(dir, y.start - x.start) / len;
+    let h1 = cross(dir, y.end - x.start) / len;
+    let dh = h1 - h0;
 
-pub fn solve_no_x_overlap(y_overlap: Range<f64>, x: &Line, slope: &f64) -> (Point, Point) {
-    let (known_x, known_y) = x.points().0.x_y();
-    let b = known_y - (slope * known_x); // Corrected calculation of b
-
-    // create bindings to x vars that will be set in if statement
-    let x1;
-    let x2;
-    
-    // handle undefined slope
-    if slope.is_infinite() || slope.is_nan() {
-        // Assign a constant value to x1 and x2
-        x1 = known_x;
-        x2 = known_x;
-    } else {
-        x1 = (y_overlap.start - b) / slope;
-        x2 = (y_overlap.end - b) / slope;
+    if dh == 0.0 {
+        return (h0.abs() <= tolerance).then_some(0.0..1.0);
     }
-    let p1 = Point::new(x1, y_overlap.start);
-    let p2 = Point::new(x2, y_overlap.end);
-    (p1, p2)  
-}
 
-pub fn solve_known_overlaps(
-    x_overlap: Range<f64>, 
-    y_overlap: Range<f64>, 
-    xbb : &Rect) -> (Point, Point) {
-
-        let dy = solve_dy(y_overlap.clone());
-        // this is the width of the overlapping bbox
-        // let base_w = x_overlap.end - x_overlap.start;
-        // this is the heeight of the bbox around xi itself
-        // _not_ the bbox of the overlapping area
-        let (base_w, base_h) = wh(&xbb);
-        // this is the length of the line from the side of the bbox
-        // to the end of the line segment
-        let dx = solve_dx(dy, base_w, base_h);
-
-        let x1 = x_overlap.end - dx;
-        let p1 = Point::new(x1, y_overlap.start);
-        let p2 = Point::new(x_overlap.end, y_overlap.end);
-        (p1, p2)
+    // solve h0 + s * dh = -tolerance and = tolerance
+    let s1 = (-tolerance - h0) / dh;
+    let s2 = (tolerance - h0) / dh;
+    overlap_range(s1.min(s2)..s1.max(s2), 0.0..1.0)
 }
 
+/// Calculate the portion of `x` that is shared with `y`.
+///
+/// `y` is clipped to the corridor within `tolerance` of the line through `x`
+/// and the clipped part is projected onto `x`. The shared portion is where
+/// the projection falls within `x`. Coordinates must be in a planar CRS.
+///
+/// Returns `None` if `x` has no length or no length of `x` is shared, such
+/// as when the segments only touch at an endpoint.
+pub fn segment_overlap(x: &Line, y: &Line, tolerance: f64) -> Option<SegmentOverlap> {
+    let dir = x.delta();
+    let len_sq = dot(dir, dir);
+    if len_sq == 0.0 {
+        return None;
+    }
 
-// get height and width from a Line
-// do this by passing in the bounding rectangle
-// (width, height)
-pub fn wh(x: &Rect) -> (f64, f64) {
-    let (x1, y1) = x.min().x_y();
-    let (x2, y2) = x.max().x_y();
-    (x2 - x1, y2 - y1)
-}
+    let corridor = corridor_range(x, y, tolerance)?;
 
-// Solve for dy:
-// This is the height of the range of Y values
-pub fn solve_dy(y_range: Range<f64>) -> f64 {
-    y_range.end - y_range.start
-}
+    // position of a point of y along x as a fraction of x
+    let along = |s: f64| dot(y.start + y.delta() * s - x.start, dir) / len_sq;
+    let (t_start, t_end) = (along(corridor.start), along(corridor.end));
+    let shared = overlap_range(t_start.min(t_end)..t_start.max(t_end), 0.0..1.0)?;
+    // segments touching at a point share no length
+    if shared.end - shared.start <= MIN_SHARED_FRACTION {
+        return None;
+    }
 
-// base_w is dx2 (the )
-fn solve_dx(dy: f64, base_w: f64, base_h: f64) -> f64 {
-    dy * base_w / base_h
+    // map the shared portion back to fractions along y
+    let dt = t_end - t_start;
+    let (target_start, target_end) = if dt == 0.0 {
+        (corridor.start, corridor.end)
+    } else {
+        let to_s = |t: f64| corridor.start + (t - t_start) / dt * (corridor.end - corridor.start);
+        let (a, b) = (to_s(shared.start), to_s(shared.end));
+        (a.min(b), a.max(b))
+    };
+
+    Some(SegmentOverlap {
+        source_start: shared.start,
+        source_end: shared.end,
+        target_start,
+        target_end,
+        length: (shared.end - shared.start) * len_sq.sqrt(),
+    })
 }
 
-fn solve_h(dx: f64, dy: f64) -> f64 {
-    (dx.powi(2) + dy.powi(2)).sqrt()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(x0: f64, y0: f64, x1: f64, y1: f64) -> Line {
+        Line::new(Coord { x: x0, y: y0 }, Coord { x: x1, y: y1 })
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn diagonal_offset_overlap() {
+        // y runs alongside the middle of x, 1 unit to the side
+        let x = line(0.0, 0.0, 10.0, 10.0);
+        let y = line(2.0, 4.0, 7.0, 9.0);
+        let o = segment_overlap(&x, &y, 2.0).unwrap();
+        assert_close(o.source_start, 0.3);
+        assert_close(o.source_end, 0.8);
+        assert_close(o.target_start, 0.0);
+        assert_close(o.target_end, 1.0);
+        assert_close(o.length, 50f64.sqrt());
+    }
+
+    #[test]
+    fn antiparallel_overlap() {
+        let x = line(0.0, 0.0, 10.0, 0.0);
+        let y = line(12.0, 1.0, 4.0, 1.0);
+        let o = segment_overlap(&x, &y, 2.0).unwrap();
+        assert_close(o.source_start, 0.4);
+        assert_close(o.source_end, 1.0);
+        // the shared part of y runs from x = 10 to x = 4
+        assert_close(o.target_start, 0.25);
+        assert_close(o.target_end, 1.0);
+        assert_close(o.length, 6.0);
+    }
+
+    #[test]
+    fn crossing_overlap_is_limited_to_the_corridor() {
+        // y crosses x at (5, 0) at 45 degrees
+        let x = line(0.0, 0.0, 10.0, 0.0);
+        let y = line(0.0, -5.0, 10.0, 5.0);
+        let o = segment_overlap(&x, &y, 1.0).unwrap();
+        assert_close(o.source_start, 0.4);
+        assert_close(o.source_end, 0.6);
+        assert_close(o.target_start, 0.4);
+        assert_close(o.target_end, 0.6);
+        assert_close(o.length, 2.0);
+    }
+
+    #[test]
+    fn touching_segments_share_nothing() {
+        // consecutive collinear edges
+        let x = line(0.0, 0.0, 10.0, 0.0);
+        let y = line(10.0, 0.0, 20.0, 0.0);
+        assert_eq!(segment_overlap(&x, &y, 2.0), None);
+        assert_eq!(segment_overlap(&y, &x, 2.0), None);
+        // perpendicular segments meeting at an endpoint
+        let z = line(10.0, 0.0, 10.0, 10.0);
+        assert_eq!(segment_overlap(&x, &z, 2.0), None);
+    }
+
+    #[test]
+    fn distant_segments_share_nothing() {
+        let x = line(0.0, 0.0, 10.0, 0.0);
+        let y = line(0.0, 5.0, 10.0, 5.0);
+        assert_eq!(segment_overlap(&x, &y, 2.0), None);
+    }
 }
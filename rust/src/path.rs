@@ -0,0 +1,199 @@
+use crate::topology::Topology;
+
+/// Costs of moving between target edges along a path, as negative log
+/// probabilities.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathWeights {
+    /// Cost of moving to an edge connected to the current edge
+    pub switch_penalty: f64,
+    /// Cost of moving to an edge not connected to the current edge
+    pub gap_penalty: f64,
+}
+
+impl Default for PathWeights {
+    fn default() -> Self {
+        Self {
+            switch_penalty: 1.0,
+            gap_penalty: 10.0,
+        }
+    }
+}
+
+/// A target edge on a matched path and the length of the source shared with it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathStep {
+    pub j: usize,
+    pub shared_len: f64,
+}
+
+/// The ordered target edges a source feature `i` follows
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedPath {
+    pub i: usize,
+    pub steps: Vec<PathStep>,
+}
+
+/// A candidate target edge for one observation of a trace
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathCandidate {
+    pub j: usize,
+    /// Cost of observing the trace on this edge, as a negative log probability
+    pub cost: f64,
+    pub shared_len: f64,
+}
+
+/// Find the most likely sequence of target edges for an ordered trace.
+///
+/// Each observation holds the candidate edges for one step of the trace.
+/// Observations without candidates are skipped. Staying on an edge is free,
+/// moving to a connected edge costs [PathWeights::switch_penalty] and moving
+/// anywhere else [PathWeights::gap_penalty]. Consecutive observations on
+/// the same edge are merged into one [PathStep].
+pub fn viterbi_path(
+    observations: &[Vec<PathCandidate>],
+    topology: &Topology,
+    weights: PathWeights,
+) -> Vec<PathStep> {
+    let observations = observations
+        .iter()
+        .filter(|o| !o.is_empty())
+        .collect::<Vec<_>>();
+    let Some(first) = observations.first() else {
+        return Vec::new();
+    };
+
+    let transition = |from: usize, to: usize| {
+        if from == to {
+            0.0
+        } else if topology.neighbours(from).binary_search(&to).is_ok() {
+            weights.switch_penalty
+        } else {
+            weights.gap_penalty
+        }
+    };
+
+    // lowest cost of ending at each candidate and the candidate before it
+    let mut costs = first.iter().map(|c| c.cost).collect::<Vec<_>>();
+    let mut back: Vec<Vec<usize>> = vec![Vec::new()];
+    observations.windows(2).for_each(|w| {
+        let (prev, next) = (w[0], w[1]);
+        let (next_costs, from): (Vec<f64>, Vec<usize>) = next
+            .iter()
+            .map(|c| {
+                let (p, cost) = prev
+                    .iter()
+                    .enumerate()
+                    .map(|(p, pc)| (p, costs[p] + transition(pc.j, c.j)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .expect("observations are not empty");
+                (cost + c.cost, p)
+            })
+            .unzip();
+        costs = next_costs;
+        back.push(from);
+    });
+
+    // trace the best path back from the cheapest final candidate
+    let mut state = costs
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(s, _)| s)
+        .expect("observations are not empty");
+    let mut chosen = Vec::with_capacity(observations.len());
+    (0..observations.len()).rev().for_each(|t| {
+        chosen.push(observations[t][state]);
+        if t > 0 {
+            state = back[t][state];
+        }
+    });
+    chosen.reverse();
+
+    let mut steps: Vec<PathStep> = Vec::new();
+    chosen.into_iter().for_each(|c| match steps.last_mut() {
+        Some(last) if last.j == c.j => last.shared_len += c.shared_len,
+        _ => steps.push(PathStep {
+            j: c.j,
+            shared_len: c.shared_len,
+        }),
+    });
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::{LineString, MultiLineString};
+
+    fn candidate(j: usize, cost: f64, shared_len: f64) -> PathCandidate {
+        PathCandidate {
+            j,
+            cost,
+            shared_len,
+        }
+    }
+
+    // edges 0, 1 and 2 in a row and edge 3 on its own
+    fn network() -> Topology {
+        let edge = |coords: Vec<(f64, f64)>| MultiLineString::new(vec![LineString::from(coords)]);
+        Topology::new(&[
+            edge(vec![(0.0, 0.0), (10.0, 0.0)]),
+            edge(vec![(10.0, 0.0), (20.0, 0.0)]),
+            edge(vec![(20.0, 0.0), (30.0, 0.0)]),
+            edge(vec![(10.0, 5.0), (20.0, 5.0)]),
+        ])
+    }
+
+    #[test]
+    fn follows_connected_edges_over_cheaper_jumps() {
+        // edge 3 is the closest edge to the middle observation but jumping
+        // to it and back costs two gaps
+        let observations = vec![
+            vec![candidate(0, 0.0, 10.0)],
+            vec![candidate(1, 2.0, 10.0), candidate(3, 0.5, 10.0)],
+            vec![candidate(2, 0.0, 10.0)],
+        ];
+        let steps = viterbi_path(&observations, &network(), PathWeights::default());
+        assert_eq!(steps.iter().map(|s| s.j).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn takes_a_jump_when_it_is_cheaper() {
+        let observations = vec![
+            vec![candidate(0, 0.0, 10.0)],
+            vec![candidate(1, 20.0, 10.0), candidate(3, 0.0, 10.0)],
+        ];
+        let steps = viterbi_path(&observations, &network(), PathWeights::default());
+        assert_eq!(steps.iter().map(|s| s.j).collect::<Vec<_>>(), vec![0, 3]);
+    }
+
+    #[test]
+    fn merges_observations_on_the_same_edge() {
+        let observations = vec![
+            vec![candidate(0, 0.0, 4.0)],
+            Vec::new(),
+            vec![candidate(0, 0.0, 6.0)],
+            vec![candidate(1, 0.0, 3.0)],
+        ];
+        let steps = viterbi_path(&observations, &network(), PathWeights::default());
+        assert_eq!(
+            steps,
+            vec![
+                PathStep {
+                    j: 0,
+                    shared_len: 10.0
+                },
+                PathStep {
+                    j: 1,
+                    shared_len: 3.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_candidates_give_no_path() {
+        let steps = viterbi_path(&[Vec::new()], &network(), PathWeights::default());
+        assert!(steps.is_empty());
+    }
+}
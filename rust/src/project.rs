@@ -0,0 +1,95 @@
+use geo::BoundingRect;
+use geo::MapCoords;
+use geo_types::{Coord, MultiLineString, Rect};
+use proj4rs::proj::Proj;
+
+/// A local metric projection for longitude and latitude coordinates.
+///
+/// Uses an azimuthal equidistant projection on the WGS84 ellipsoid centred
+/// on the extent of the data. Distances are in metres and are close to true
+/// distances for networks up to national scale.
+pub struct LocalProjection {
+    from: Proj,
+    to: Proj,
+    center: Coord,
+}
+
+impl LocalProjection {
+    /// Create a projection centred on the coordinate `center`
+    pub fn new(center: Coord) -> Self {
+        let from = Proj::from_proj_string("+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs")
+            .expect("longlat projection is valid");
+        let to = Proj::from_proj_string(&format!(
+            "+proj=aeqd +lat_0={} +lon_0={} +x_0=0 +y_0=0 +ellps=WGS84 +datum=WGS84 +units=m +no_defs",
+            center.y, center.x
+        ))
+        .expect("aeqd projection is valid");
+        Self { from, to, center }
+    }
+
+    /// Create a projection centred on the bounding box of `lines`.
+    /// Returns `None` if there are no coordinates.
+    pub fn from_extent<'a>(lines: impl Iterator<Item = &'a MultiLineString>) -> Option<Self> {
+        let extent = lines.filter_map(|l| l.bounding_rect()).reduce(|a, b| {
+            Rect::new(
+                Coord {
+                    x: a.min().x.min(b.min().x),
+                    y: a.min().y.min(b.min().y),
+                },
+                Coord {
+                    x: a.max().x.max(b.max().x),
+                    y: a.max().y.max(b.max().y),
+                },
+            )
+        })?;
+        Some(Self::new(extent.center()))
+    }
+
+    /// The longitude and latitude of the projection centre
+    pub fn center(&self) -> Coord {
+        self.center
+    }
+
+    /// Project a feature from longitude and latitude to metres.
+    ///
+    /// Panics if a coordinate is not a valid longitude and latitude.
+    pub fn project(&self, x: &MultiLineString) -> MultiLineString {
+        x.map_coords(|c| {
+            let mut p = (c.x.to_radians(), c.y.to_radians(), 0.0);
+            proj4rs::transform::transform(&self.from, &self.to, &mut p)
+                .expect("coordinates must be valid longitude and latitude");
+            Coord { x: p.0, y: p.1 }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::line_string;
+
+    #[test]
+    fn no_coordinates_have_no_projection() {
+        assert!(LocalProjection::from_extent(std::iter::empty()).is_none());
+        let empty = [MultiLineString::new(vec![])];
+        assert!(LocalProjection::from_extent(empty.iter()).is_none());
+    }
+
+    #[test]
+    fn projection_is_centred_on_the_extent() {
+        let x = [
+            MultiLineString::new(vec![]),
+            MultiLineString::new(vec![line_string![(x: -2.0, y: 53.0), (x: -1.0, y: 53.5)]]),
+            MultiLineString::new(vec![line_string![(x: -1.5, y: 54.0), (x: 0.0, y: 53.8)]]),
+        ];
+        let projection = LocalProjection::from_extent(x.iter()).unwrap();
+        assert_eq!(projection.center(), Coord { x: -1.0, y: 53.5 });
+
+        // the centre projects to the origin
+        let centre =
+            MultiLineString::new(vec![line_string![(x: -1.0, y: 53.5), (x: -1.0, y: 53.5)]]);
+        let projected = projection.project(&centre);
+        let c = projected.0[0].0[0];
+        assert!(c.x.abs() < 1e-6 && c.y.abs() < 1e-6, "{c:?}");
+    }
+}
@@ -0,0 +1,113 @@
+use crate::geometry::LinePortion;
+use crate::table::SegmentMatch;
+use geo_types::{Line, LineString, MultiLineString};
+use std::collections::BTreeMap;
+
+/// The pieces of the component lines of each feature
+pub type FeaturePieces = Vec<Vec<LinePortion>>;
+
+/// Split every component line of `x` into equal pieces no longer than
+/// `max_len`, as measured by `length`.
+///
+/// Returns the densified feature and, for each of its component lines, the
+/// original component line it is part of and the fractions along that line
+/// it covers.
+pub fn segmentize(
+    x: &MultiLineString,
+    max_len: f64,
+    length: impl Fn(&Line) -> f64,
+) -> (MultiLineString, Vec<LinePortion>) {
+    let mut pieces = Vec::new();
+    let mut k = 0;
+    let parts = x
+        .iter()
+        .map(|part| {
+            let mut coords = part.0.first().into_iter().copied().collect::<Vec<_>>();
+            part.lines().for_each(|line| {
+                let n = (length(&line) / max_len).ceil().max(1.0) as usize;
+                (1..=n).for_each(|p| {
+                    let t = p as f64 / n as f64;
+                    coords.push(line.start + line.delta() * t);
+                    pieces.push((k, (p - 1) as f64 / n as f64, t));
+                });
+                k += 1;
+            });
+            LineString::new(coords)
+        })
+        .collect();
+    (MultiLineString::new(parts), pieces)
+}
+
+/// Map segment matches between pieces of segmentized features back to the
+/// original component lines and merge those of the same pair of lines.
+///
+/// `x_pieces` and `y_pieces` are the pieces of each source and target
+/// feature as returned by [segmentize].
+pub fn merge_pieces(
+    segments: Vec<SegmentMatch>,
+    x_pieces: &[Vec<LinePortion>],
+    y_pieces: &[Vec<LinePortion>],
+) -> Vec<SegmentMatch> {
+    let mapped = segments.into_iter().map(|s| {
+        let (k, x_from, x_to) = x_pieces[s.i][s.source_segment];
+        let (l, y_from, y_to) = y_pieces[s.j][s.target_segment];
+        let on_x = |t: f64| x_from + t * (x_to - x_from);
+        let on_y = |t: f64| y_from + t * (y_to - y_from);
+
+        let mut m = s;
+        m.source_segment = k;
+        m.target_segment = l;
+        m.overlap.source_start = on_x(s.overlap.source_start);
+        m.overlap.source_end = on_x(s.overlap.source_end);
+        m.overlap.target_start = on_y(s.overlap.target_start);
+        m.overlap.target_end = on_y(s.overlap.target_end);
+        m
+    });
+    merge_line_matches(mapped)
+}
+
+/// Merge segment matches of the same pair of component lines.
+///
+/// The shared portions span all of the merged matches and lengths are
+/// summed. The separation and angle difference are means weighted by
+/// shared length.
+pub fn merge_line_matches(segments: impl Iterator<Item = SegmentMatch>) -> Vec<SegmentMatch> {
+    // the merged match and the separation and angle of the first match,
+    // used when there is no shared length to weight by
+    let mut merged: BTreeMap<_, (SegmentMatch, f64, f64)> = BTreeMap::new();
+    segments.for_each(|s| {
+        let mut m = s;
+        // weighted by length, divided by the merged length below
+        m.separation *= s.overlap.length;
+        m.angle_diff *= s.overlap.length;
+
+        merged
+            .entry((m.i, m.source_segment, m.j, m.target_segment))
+            .and_modify(|(a, _, _)| {
+                a.overlap.source_start = a.overlap.source_start.min(m.overlap.source_start);
+                a.overlap.source_end = a.overlap.source_end.max(m.overlap.source_end);
+                a.overlap.target_start = a.overlap.target_start.min(m.overlap.target_start);
+                a.overlap.target_end = a.overlap.target_end.max(m.overlap.target_end);
+                a.overlap.length += m.overlap.length;
+                a.distance = a.distance.min(m.distance);
+                a.separation += m.separation;
+                a.angle_diff += m.angle_diff;
+                a.target_shared_len += m.target_shared_len;
+            })
+            .or_insert((m, s.separation, s.angle_diff));
+    });
+
+    merged
+        .into_values()
+        .map(|(mut m, separation, angle_diff)| {
+            if m.overlap.length > 0.0 {
+                m.separation /= m.overlap.length;
+                m.angle_diff /= m.overlap.length;
+            } else {
+                m.separation = separation;
+                m.angle_diff = angle_diff;
+            }
+            m
+        })
+        .collect()
+}
@@ -0,0 +1,262 @@
+use crate::segmentize::merge_line_matches;
+use crate::structs::feature_lines;
+use crate::table::SegmentMatch;
+use geo::{SimplifyIdx, SimplifyVwIdx};
+use geo_types::{Line, LineString, MultiLineString};
+
+/// Algorithm used to simplify features before matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimplifyMethod {
+    /// Ramer–Douglas–Peucker. Removes vertices closer than the tolerance to
+    /// the simplified line.
+    DouglasPeucker,
+    /// Visvalingam–Whyatt. Removes vertices forming triangles with an area
+    /// smaller than the square of the tolerance.
+    Visvalingam,
+}
+
+/// Simplification of features before matching.
+///
+/// The tolerance is `factor` times the distance tolerance of each feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Simplification {
+    pub method: SimplifyMethod,
+    pub factor: f64,
+}
+
+/// The original component lines covered by one simplified component line
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineSpan {
+    /// Index of the first original component line
+    pub first: usize,
+    /// Fractions along the simplified line at which each original line
+    /// starts, followed by 1, in proportion to the original lengths
+    pub breaks: Vec<f64>,
+    /// Length of the original lines
+    pub length: f64,
+}
+
+impl LineSpan {
+    /// Position among the original lines of the line at fraction `t` of
+    /// the simplified line
+    fn line_at(&self, t: f64) -> usize {
+        let n = self.breaks.len() - 1;
+        self.breaks
+            .partition_point(|&b| b <= t)
+            .saturating_sub(1)
+            .min(n - 1)
+    }
+
+    /// Fraction along the `m`th original line of fraction `t` of the
+    /// simplified line
+    fn fraction(&self, m: usize, t: f64) -> f64 {
+        let width = self.breaks[m + 1] - self.breaks[m];
+        match width > 0.0 {
+            true => ((t - self.breaks[m]) / width).clamp(0.0, 1.0),
+            false => 0.0,
+        }
+    }
+}
+
+/// The spans of the simplified component lines of each feature
+pub type FeatureSpans = Vec<Vec<LineSpan>>;
+
+/// Simplify each part of `x` with a tolerance of `epsilon`.
+///
+/// Returns the simplified feature and the original component lines covered
+/// by each of its component lines, with lengths measured by `length`. Parts
+/// with fewer than two coordinates are kept as they are and have no spans.
+pub fn simplify_feature(
+    x: &MultiLineString,
+    epsilon: f64,
+    method: SimplifyMethod,
+    length: impl Fn(&Line) -> f64,
+) -> (MultiLineString, Vec<LineSpan>) {
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    let parts = x
+        .iter()
+        .map(|part| {
+            // a part without lines has nothing to simplify or match
+            if part.0.len() < 2 {
+                return part.clone();
+            }
+            let kept = match method {
+                SimplifyMethod::DouglasPeucker => part.simplify_idx(&epsilon),
+                SimplifyMethod::Visvalingam => part.simplify_vw_idx(&(epsilon * epsilon)),
+            };
+            let lens = part.lines().map(|l| length(&l)).collect::<Vec<_>>();
+            kept.windows(2).for_each(|w| {
+                let span_lens = &lens[w[0]..w[1]];
+                let total = span_lens.iter().sum::<f64>();
+                let n = span_lens.len() as f64;
+                let mut cumulative = 0.0;
+                let breaks = std::iter::once(0.0)
+                    .chain(span_lens.iter().enumerate().map(|(m, l)| {
+                        cumulative += l;
+                        // split evenly when the original lines have no length
+                        match total > 0.0 {
+                            true => cumulative / total,
+                            false => (m + 1) as f64 / n,
+                        }
+                    }))
+                    .collect();
+                spans.push(LineSpan {
+                    first: offset + w[0],
+                    breaks,
+                    length: total,
+                });
+            });
+            offset += lens.len();
+            LineString::new(kept.iter().map(|&v| part.0[v]).collect())
+        })
+        .collect();
+    (MultiLineString::new(parts), spans)
+}
+
+/// Map segment matches between simplified features back to the original
+/// component lines.
+///
+/// A match is split wherever either simplified line passes an original
+/// vertex. Shared lengths are scaled to the length of the original lines.
+/// `x` and `y` are the simplified features and `x_spans` and `y_spans` the
+/// spans returned by [simplify_feature].
+pub fn expand_spans(
+    segments: Vec<SegmentMatch>,
+    x: &[MultiLineString],
+    y: &[MultiLineString],
+    x_spans: &[Vec<LineSpan>],
+    y_spans: &[Vec<LineSpan>],
+) -> Vec<SegmentMatch> {
+    let x_lines = x
+        .iter()
+        .map(|f| feature_lines(f).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let y_lines = y
+        .iter()
+        .map(|f| feature_lines(f).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let expanded = segments.into_iter().flat_map(|s| {
+        let x_span = &x_spans[s.i][s.source_segment];
+        let y_span = &y_spans[s.j][s.target_segment];
+        let (s0, s1) = (s.overlap.source_start, s.overlap.source_end);
+        let (t0, t1) = (s.overlap.target_start, s.overlap.target_end);
+
+        // position on the target for a position on the source. the target
+        // runs backwards when the lines point in opposite directions
+        let (dx, dy) = (
+            x_lines[s.i][s.source_segment].delta(),
+            y_lines[s.j][s.target_segment].delta(),
+        );
+        let forwards = dx.x * dy.x + dx.y * dy.y >= 0.0;
+        let to_target = |u: f64| {
+            let r = match s1 > s0 {
+                true => (u - s0) / (s1 - s0),
+                false => 0.0,
+            };
+            match forwards {
+                true => t0 + r * (t1 - t0),
+                false => t1 - r * (t1 - t0),
+            }
+        };
+        let to_source = |v: f64| {
+            let r = (v - t0) / (t1 - t0);
+            match forwards {
+                true => s0 + r * (s1 - s0),
+                false => s1 - r * (s1 - s0),
+            }
+        };
+
+        let mut cuts = vec![s0, s1];
+        cuts.extend(x_span.breaks.iter().filter(|&&b| b > s0 && b < s1));
+        if t1 > t0 {
+            cuts.extend(
+                y_span
+                    .breaks
+                    .iter()
+                    .filter(|&&b| b > t0 && b < t1)
+                    .map(|&b| to_source(b)),
+            );
+        }
+        cuts.sort_by(f64::total_cmp);
+        cuts.dedup();
+
+        let pieces = match cuts.len() {
+            // the shared portion is a single point
+            1 => vec![(s0, s0)],
+            _ => cuts.windows(2).map(|w| (w[0], w[1])).collect(),
+        };
+        pieces
+            .into_iter()
+            .map(|(ua, ub)| {
+                let (va, vb) = (to_target(ua), to_target(ub));
+                let mx = x_span.line_at((ua + ub) / 2.0);
+                let my = y_span.line_at((va + vb) / 2.0);
+
+                let mut m = s;
+                m.source_segment = x_span.first + mx;
+                m.target_segment = y_span.first + my;
+                m.overlap.source_start = x_span.fraction(mx, ua);
+                m.overlap.source_end = x_span.fraction(mx, ub);
+                m.overlap.target_start = y_span.fraction(my, va.min(vb));
+                m.overlap.target_end = y_span.fraction(my, va.max(vb));
+                m.overlap.length = (ub - ua) * x_span.length;
+                m.target_shared_len = (vb - va).abs() * y_span.length;
+                m
+            })
+            .collect::<Vec<_>>()
+    });
+    merge_line_matches(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::EuclideanLength;
+    use geo_types::line_string;
+
+    #[test]
+    fn spans_cover_the_removed_vertices() {
+        let x = MultiLineString::new(vec![line_string![
+            (x: 0., y: 0.), (x: 10., y: 0.), (x: 40., y: 0.), (x: 40., y: 10.)
+        ]]);
+        let (simplified, spans) = simplify_feature(&x, 0.5, SimplifyMethod::DouglasPeucker, |l| {
+            l.euclidean_length()
+        });
+        assert_eq!(simplified.0[0].0.len(), 3);
+        assert_eq!(
+            spans,
+            vec![
+                LineSpan {
+                    first: 0,
+                    breaks: vec![0.0, 0.25, 1.0],
+                    length: 40.0
+                },
+                LineSpan {
+                    first: 2,
+                    breaks: vec![0.0, 1.0],
+                    length: 10.0
+                },
+            ]
+        );
+        assert_eq!(spans[0].line_at(0.2), 0);
+        assert_eq!(spans[0].line_at(0.5), 1);
+        assert_eq!(spans[0].fraction(1, 0.625), 0.5);
+    }
+
+    #[test]
+    fn single_coordinate_parts_are_kept() {
+        let x = MultiLineString::new(vec![
+            line_string![(x: 5., y: 5.)],
+            line_string![(x: 0., y: 0.), (x: 10., y: 0.), (x: 20., y: 0.)],
+        ]);
+        for method in [SimplifyMethod::DouglasPeucker, SimplifyMethod::Visvalingam] {
+            let (simplified, spans) = simplify_feature(&x, 0.5, method, |l| l.euclidean_length());
+            assert_eq!(simplified.0[0], x.0[0]);
+            assert_eq!(simplified.0[1].0.len(), 2);
+            assert_eq!(spans.len(), 1);
+            assert_eq!(spans[0].first, 0);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use geo_types::{Coord, Line};
+
+/// Mean earth radius in metres, the same value used by
+/// [geo::HaversineDistance]
+pub const MEAN_EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Convert a distance in metres to `(longitude, latitude)` offsets in degrees.
+///
+/// The latitude offset is constant. The longitude offset grows towards the poles
+/// and is calculated at `lat` shifted poleward by the latitude offset so that it
+/// covers the distance anywhere within it. It is capped at 180 degrees.
+pub fn degree_offsets(dist: f64, lat: f64) -> (f64, f64) {
+    let dy = (dist / MEAN_EARTH_RADIUS).to_degrees();
+    let edge_lat = (lat.abs() + dy).min(90.0).to_radians();
+    let dx = (dy / edge_lat.cos()).min(180.0);
+    (dx, dy)
+}
+
+/// Convert a lon/lat coordinate to metres east and north of `origin`.
+///
+/// Uses an equirectangular approximation at the latitude of `origin`
+/// which is accurate for the short distances between matched segments.
+pub fn to_local_metres(c: Coord, origin: Coord) -> Coord {
+    let metres_per_degree = MEAN_EARTH_RADIUS * std::f64::consts::PI / 180.0;
+    Coord {
+        x: (c.x - origin.x) * origin.y.to_radians().cos() * metres_per_degree,
+        y: (c.y - origin.y) * metres_per_degree,
+    }
+}
+
+// Unit vectors on the sphere are represented as [x, y, z]
+type Vec3 = [f64; 3];
+
+/// Convert a lon/lat coordinate in degrees to a unit vector
+fn to_vec3(c: Coord) -> Vec3 {
+    let (lon, lat) = (c.x.to_radians(), c.y.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: Vec3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn neg(a: Vec3) -> Vec3 {
+    [-a[0], -a[1], -a[2]]
+}
+
+/// Central angle between two unit vectors in radians.
+/// Uses atan2 which stays accurate for very small angles.
+fn angle(a: Vec3, b: Vec3) -> f64 {
+    norm(cross(a, b)).atan2(dot(a, b))
+}
+
+/// Whether `p`, which lies on the great circle with normal `n` through
+/// `a` and `b`, falls between `a` and `b`
+fn within_arc(p: Vec3, a: Vec3, b: Vec3, n: Vec3) -> bool {
+    dot(cross(a, p), n) >= 0.0 && dot(cross(p, b), n) >= 0.0
+}
+
+/// Great circle distance in metres from a point to a great circle arc.
+///
+/// When the perpendicular from `p` falls inside the arc this is the
+/// cross-track distance. Otherwise it is the distance to the nearest endpoint.
+pub fn haversine_point_line_distance(p: Coord, line: &Line) -> f64 {
+    let (a, b, p) = (to_vec3(line.start), to_vec3(line.end), to_vec3(p));
+    point_arc_angle(p, a, b) * MEAN_EARTH_RADIUS
+}
+
+fn point_arc_angle(p: Vec3, a: Vec3, b: Vec3) -> f64 {
+    let n = cross(a, b);
+    let n_len = norm(n);
+    let endpoint_angle = angle(p, a).min(angle(p, b));
+
+    // degenerate arc: the endpoints are the same point
+    if n_len <= f64::EPSILON {
+        return endpoint_angle;
+    }
+
+    let n = [n[0] / n_len, n[1] / n_len, n[2] / n_len];
+
+    // project p onto the plane of the great circle to find the
+    // closest point on the full circle
+    let d = dot(p, n);
+    let proj = [p[0] - d * n[0], p[1] - d * n[1], p[2] - d * n[2]];
+
+    if norm(proj) > f64::EPSILON && within_arc(proj, a, b, n) {
+        d.clamp(-1.0, 1.0).asin().abs()
+    } else {
+        endpoint_angle
+    }
+}
+
+/// Great circle distance in metres between two great circle arcs.
+///
+/// The distance is 0 when the arcs cross. Otherwise it is the smallest
+/// distance from any endpoint to the other arc.
+pub fn haversine_line_distance(x: &Line, y: &Line) -> f64 {
+    let (a1, a2) = (to_vec3(x.start), to_vec3(x.end));
+    let (b1, b2) = (to_vec3(y.start), to_vec3(y.end));
+
+    if arcs_intersect(a1, a2, b1, b2) {
+        return 0.0;
+    }
+
+    let min_angle = point_arc_angle(a1, b1, b2)
+        .min(point_arc_angle(a2, b1, b2))
+        .min(point_arc_angle(b1, a1, a2))
+        .min(point_arc_angle(b2, a1, a2));
+
+    min_angle * MEAN_EARTH_RADIUS
+}
+
+fn arcs_intersect(a1: Vec3, a2: Vec3, b1: Vec3, b2: Vec3) -> bool {
+    let na = cross(a1, a2);
+    let nb = cross(b1, b2);
+    let l = cross(na, nb);
+    let l_len = norm(l);
+
+    // arcs on the same great circle or degenerate arcs are handled
+    // by the endpoint distances
+    if l_len <= f64::EPSILON {
+        return false;
+    }
+
+    // the two great circles meet at l and its antipode
+    let c = [l[0] / l_len, l[1] / l_len, l[2] / l_len];
+    [c, neg(c)]
+        .into_iter()
+        .any(|p| within_arc(p, a1, a2, na) && within_arc(p, b1, b2, nb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arc(x0: f64, y0: f64, x1: f64, y1: f64) -> Line {
+        Line::new(Coord { x: x0, y: y0 }, Coord { x: x1, y: y1 })
+    }
+
+    fn metres(degrees: f64) -> f64 {
+        degrees.to_radians() * MEAN_EARTH_RADIUS
+    }
+
+    #[test]
+    fn offset_arcs() {
+        // an arc along the equator and one starting 0.01 degrees north of it
+        let x = arc(0.0, 0.0, 1.0, 0.0);
+        let y = arc(0.0, 0.01, 1.0, 0.01);
+        let d = haversine_line_distance(&x, &y);
+        assert!((d - metres(0.01)).abs() < 1e-6, "{d}");
+        assert_eq!(d, haversine_line_distance(&y, &x));
+    }
+
+    #[test]
+    fn offset_arcs_beyond_the_end() {
+        // y starts 0.5 degrees past the end of x on the same great circle
+        let x = arc(0.0, 0.0, 1.0, 0.0);
+        let y = arc(1.5, 0.0, 2.0, 0.0);
+        let d = haversine_line_distance(&x, &y);
+        assert!((d - metres(0.5)).abs() < 1e-6, "{d}");
+    }
+
+    #[test]
+    fn crossing_arcs() {
+        let x = arc(-1.0, 0.0, 1.0, 0.0);
+        let y = arc(0.0, -1.0, 0.0, 1.0);
+        assert_eq!(haversine_line_distance(&x, &y), 0.0);
+
+        // the great circles cross outside of y
+        let y = arc(0.0, 0.2, 0.0, 1.0);
+        let d = haversine_line_distance(&x, &y);
+        assert!((d - metres(0.2)).abs() < 1e-6, "{d}");
+    }
+
+    #[test]
+    fn degree_offsets_at_the_equator() {
+        let (dx, dy) = degree_offsets(metres(0.01), 0.0);
+        assert!((dy - 0.01).abs() < 1e-12, "{dy}");
+        // widened by the latitude offset only
+        assert!(dx > dy && dx - dy < 1e-9, "{dx}");
+    }
+
+    #[test]
+    fn degree_offsets_widen_towards_the_poles() {
+        let (dx, dy) = degree_offsets(metres(0.01), 60.0);
+        assert!((dy - 0.01).abs() < 1e-12, "{dy}");
+        assert!(
+            (dx - 0.01 / 60.01_f64.to_radians().cos()).abs() < 1e-12,
+            "{dx}"
+        );
+        assert_eq!(degree_offsets(metres(0.01), -60.0), (dx, dy));
+    }
+
+    #[test]
+    fn degree_offsets_are_capped_near_the_poles() {
+        assert_eq!(degree_offsets(metres(1.0), 89.5).0, 180.0);
+        assert_eq!(degree_offsets(metres(0.01), 90.0).0, 180.0);
+    }
+}
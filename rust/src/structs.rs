@@ -1,54 +1,60 @@
-use geo::{BoundingRect, EuclideanDistance, HaversineDistance};
-use geo::{Line, Point};
+use crate::spherical::{degree_offsets, haversine_line_distance};
+use geo::{BoundingRect, EuclideanDistance};
+use geo::{Line, MultiLineString, Point};
 use rstar::RTreeObject;
 use rstar::AABB;
 
-
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CrsType {
     Projected,
     Geographic,
+    /// Longitude and latitude input that is projected to a local metric
+    /// projection before matching. See [crate::LocalProjection].
+    /// Coordinates reaching the matching steps are already projected.
+    /// Lengths are measured on the WGS84 ellipsoid and can differ from those
+    /// of [CrsType::Geographic], which uses a sphere, by up to about 0.5%.
+    AutoProject,
+}
+
+/// The component lines of a feature in order across all of its parts
+pub fn feature_lines(x: &MultiLineString) -> impl Iterator<Item = Line> + '_ {
+    x.iter().flat_map(|part| part.lines())
 }
 
 /// Custom struct to be used to insert into RTree
-/// Represents a component `Line` of a target `LineString`.
-/// The tuple stores the `Line` struct and the distance buffer to be used.
+/// Represents a component `Line` of a target feature.
+/// The tuple stores the `Line` struct, the distance buffer to be used and the
+/// CRS type of the coordinates.
 /// It's [rstar::Envelope] method grows the [rstar::AABB] in x and y directions
-/// by the distance.
-pub struct TarLine(pub Line<f64>, pub f64);
+/// by the distance. For a geographic CRS the distance is in metres and is
+/// converted to degrees at the latitude of the line.
+pub struct TarLine(pub Line<f64>, pub f64, pub CrsType);
 impl TarLine {
     /// Create an AABB from the contained `Line`
     pub fn envelope(&self) -> AABB<Point> {
-        let padding_dist = self.1;
         let bb = self.0.bounding_rect();
         let (ll_x, ll_y) = bb.min().x_y();
         let (ur_x, ur_y) = bb.max().x_y();
-        let ll = Point::new(ll_x - padding_dist, ll_y - padding_dist);
-        let ur = Point::new(ur_x + padding_dist, ur_y + padding_dist);
+        let (pad_x, pad_y) = match self.2 {
+            CrsType::Projected | CrsType::AutoProject => (self.1, self.1),
+            // pad by the widest longitude offset, found furthest from the equator
+            CrsType::Geographic => degree_offsets(self.1, ll_y.abs().max(ur_y.abs())),
+        };
+        let ll = Point::new(ll_x - pad_x, ll_y - pad_y);
+        let ur = Point::new(ur_x + pad_x, ur_y + pad_y);
         AABB::from_corners(ll, ur)
     }
 
-    /// Using geographic coordinate systems should be avoided with this algorithm.
-    /// Measuring distance in geographic space between two lines finds the minimum
-    /// distance between vertices whereas the euclidean distance between two lines
-    /// considers all possible distances.
-    /// Geographic distance may create false negatives.
-    pub fn dist_by_crs(&self, other: &Line, crs: &CrsType) -> f64 {
-        match crs {
-            CrsType::Projected => self.0.euclidean_distance(other),
-            CrsType::Geographic => {
-                let x = self.0;
-                x.start_point().haversine_distance(&other.start_point())
-                    .min(
-                        x.start_point().haversine_distance(&other.end_point())
-                    )
-                    .min(
-                        x.end_point().haversine_distance(&other.start_point())
-                    )
-                    .min(
-                        x.end_point().haversine_distance(&other.end_point())
-                    )
-            },
-
+    /// Distance between the contained `Line` and `other`.
+    ///
+    /// With a projected CRS this is the euclidean distance. With a geographic CRS
+    /// the lines are treated as great circle arcs and the distance is measured in
+    /// metres along the sphere, including cross-track distance from the endpoints
+    /// of one arc to the interior of the other.
+    pub fn dist_by_crs(&self, other: &Line) -> f64 {
+        match self.2 {
+            CrsType::Projected | CrsType::AutoProject => self.0.euclidean_distance(other),
+            CrsType::Geographic => haversine_line_distance(&self.0, other),
         }
     }
 }
@@ -59,3 +65,50 @@ impl RTreeObject for TarLine {
         self.envelope()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spherical::MEAN_EARTH_RADIUS;
+    use geo::Coord;
+
+    fn line(x0: f64, y0: f64, x1: f64, y1: f64) -> Line {
+        Line::new(Coord { x: x0, y: y0 }, Coord { x: x1, y: y1 })
+    }
+
+    #[test]
+    fn projected_envelope_is_padded_by_the_distance() {
+        let envelope = TarLine(line(0.0, 0.0, 10.0, 5.0), 2.0, CrsType::Projected).envelope();
+        assert_eq!(envelope.lower(), Point::new(-2.0, -2.0));
+        assert_eq!(envelope.upper(), Point::new(12.0, 7.0));
+    }
+
+    #[test]
+    fn geographic_envelope_is_padded_at_the_latitude_furthest_from_the_equator() {
+        let dist = 1000.0;
+        let envelope = TarLine(line(0.0, 50.0, 0.1, 51.0), dist, CrsType::Geographic).envelope();
+        let (dx, dy) = degree_offsets(dist, 51.0);
+        assert_eq!(envelope.lower(), Point::new(-dx, 50.0 - dy));
+        assert_eq!(envelope.upper(), Point::new(0.1 + dx, 51.0 + dy));
+
+        // covers a point `dist` metres east of the northern end
+        let east = (dist / (MEAN_EARTH_RADIUS * 51_f64.to_radians().cos())).to_degrees();
+        assert!(0.1 + east < envelope.upper().x());
+
+        // the same in the southern hemisphere
+        let envelope = TarLine(line(0.0, -51.0, 0.1, -50.0), dist, CrsType::Geographic).envelope();
+        assert_eq!(envelope.lower(), Point::new(-dx, -51.0 - dy));
+    }
+
+    #[test]
+    fn distance_follows_the_crs_of_the_line() {
+        let x = line(0.0, 0.0, 1.0, 0.0);
+        let y = line(0.0, 0.01, 1.0, 0.01);
+        assert!((TarLine(x, 0.0, CrsType::Projected).dist_by_crs(&y) - 0.01).abs() < 1e-12);
+        let d = TarLine(x, 0.0, CrsType::Geographic).dist_by_crs(&y);
+        assert!(
+            (d - 0.01_f64.to_radians() * MEAN_EARTH_RADIUS).abs() < 1e-6,
+            "{d}"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::overlap::SegmentOverlap;
+use std::collections::BTreeMap;
+
+/// A matched pair of component lines of a source and target feature.
+///
+/// `i` and `j` are the 0-based positions of the features, `source_segment`
+/// and `target_segment` the 0-based positions of the lines within them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentMatch {
+    pub i: usize,
+    pub source_segment: usize,
+    pub j: usize,
+    pub target_segment: usize,
+    /// The shared portion of the source and target lines
+    pub overlap: SegmentOverlap,
+    /// Minimum distance between the two lines
+    pub distance: f64,
+    /// Distance from the middle of the shared portion of the source line
+    /// to the target line
+    pub separation: f64,
+    /// Difference between the bearings of the lines in degrees
+    pub angle_diff: f64,
+    /// Length of the target line that is shared with the source line
+    pub target_shared_len: f64,
+}
+
+/// A single matched pair of a source and target feature.
+///
+/// `i` and `j` are 0-based positions in the source and target inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    pub i: usize,
+    pub j: usize,
+    pub shared_len: f64,
+    /// Number of matched pairs of component lines contributing to `shared_len`
+    pub n_segments: usize,
+}
+
+/// The result of matching two sets of lines.
+///
+/// Stores the accumulated shared length and number of matched component
+/// line pairs for every matched `(i, j)` pair. Pairs are ordered by source
+/// index then target index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchTable {
+    matches: BTreeMap<(usize, usize), (f64, usize)>,
+}
+
+impl MatchTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one matched pair of component lines sharing `shared_len` to the
+    /// pair `(i, j)`, inserting it if needed.
+    pub fn insert(&mut self, i: usize, j: usize, shared_len: f64) {
+        self.add(Match {
+            i,
+            j,
+            shared_len,
+            n_segments: 1,
+        });
+    }
+
+    /// Add the shared length and segment count of `m` to its pair,
+    /// inserting it if needed.
+    pub fn add(&mut self, m: Match) {
+        let (shared_len, n_segments) = self.matches.entry((m.i, m.j)).or_insert((0.0, 0));
+        *shared_len += m.shared_len;
+        *n_segments += m.n_segments;
+    }
+
+    /// Number of matched pairs
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    /// The shared length of `(i, j)` if they were matched
+    pub fn get(&self, i: usize, j: usize) -> Option<f64> {
+        self.matches.get(&(i, j)).map(|&(shared_len, _)| shared_len)
+    }
+
+    /// Iterate over all matched pairs
+    pub fn iter(&self) -> impl Iterator<Item = Match> + '_ {
+        self.matches.iter().map(to_match)
+    }
+
+    /// Iterate over the targets matched to source `i`
+    pub fn targets_of(&self, i: usize) -> impl Iterator<Item = Match> + '_ {
+        self.matches.range((i, 0)..=(i, usize::MAX)).map(to_match)
+    }
+
+    /// Iterate over the sources matched to target `j`.
+    ///
+    /// Pairs are stored by source so this scans the whole table. Use
+    /// [MatchTable::targets_of] where either will do.
+    pub fn sources_of(&self, j: usize) -> impl Iterator<Item = Match> + '_ {
+        self.iter().filter(move |m| m.j == j)
+    }
+
+    /// Split the table into `(i, j, shared_len, n_segments)` column vectors.
+    /// Indices are 0-based.
+    pub fn to_columns(&self) -> (Vec<usize>, Vec<usize>, Vec<f64>, Vec<usize>) {
+        let mut is = Vec::with_capacity(self.len());
+        let mut js = Vec::with_capacity(self.len());
+        let mut lens = Vec::with_capacity(self.len());
+        let mut counts = Vec::with_capacity(self.len());
+        self.iter().for_each(|m| {
+            is.push(m.i);
+            js.push(m.j);
+            lens.push(m.shared_len);
+            counts.push(m.n_segments);
+        });
+        (is, js, lens, counts)
+    }
+}
+
+fn to_match((&(i, j), &(shared_len, n_segments)): (&(usize, usize), &(f64, usize))) -> Match {
+    Match {
+        i,
+        j,
+        shared_len,
+        n_segments,
+    }
+}
+
+impl FromIterator<Match> for MatchTable {
+    fn from_iter<T: IntoIterator<Item = Match>>(iter: T) -> Self {
+        let mut table = MatchTable::new();
+        table.extend(iter);
+        table
+    }
+}
+
+impl Extend<Match> for MatchTable {
+    fn extend<T: IntoIterator<Item = Match>>(&mut self, iter: T) {
+        iter.into_iter().for_each(|m| self.add(m));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> MatchTable {
+        let mut table = MatchTable::new();
+        table.insert(1, 0, 5.0);
+        table.insert(0, 2, 1.0);
+        table.insert(1, 0, 2.5);
+        table.insert(0, 0, 4.0);
+        table
+    }
+
+    #[test]
+    fn insert_accumulates_pairs() {
+        let table = table();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get(1, 0), Some(7.5));
+        assert_eq!(table.get(2, 0), None);
+        let m = table.iter().find(|m| (m.i, m.j) == (1, 0)).unwrap();
+        assert_eq!(m.n_segments, 2);
+    }
+
+    #[test]
+    fn matches_are_collected_with_their_segment_counts() {
+        let table = table().iter().chain(table().iter()).collect::<MatchTable>();
+        assert_eq!(table.get(1, 0), Some(15.0));
+        assert_eq!(table.to_columns().3, vec![2, 2, 4]);
+    }
+
+    #[test]
+    fn partners_of_sources_and_targets() {
+        let table = table();
+        let targets = table.targets_of(0).map(|m| m.j).collect::<Vec<_>>();
+        assert_eq!(targets, vec![0, 2]);
+        let sources = table.sources_of(0).map(|m| m.i).collect::<Vec<_>>();
+        assert_eq!(sources, vec![0, 1]);
+        assert_eq!(table.sources_of(1).count(), 0);
+    }
+
+    #[test]
+    fn columns_are_ordered_by_source_then_target() {
+        let (is, js, lens, counts) = table().to_columns();
+        assert_eq!(is, vec![0, 0, 1]);
+        assert_eq!(js, vec![0, 2, 0]);
+        assert_eq!(lens, vec![4.0, 1.0, 7.5]);
+        assert_eq!(counts, vec![1, 1, 2]);
+    }
+}
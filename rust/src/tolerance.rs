@@ -0,0 +1,70 @@
+use crate::input::{check_count, InputError};
+use std::fmt;
+use std::sync::Arc;
+
+/// A distance tolerance for each feature of an input.
+///
+/// Given either as one value per feature, in the order of the input, or as
+/// a function from the 0-based position of a feature to its tolerance.
+#[derive(Clone)]
+pub enum FeatureTolerance {
+    Values(Vec<f64>),
+    Fn(Arc<dyn Fn(usize) -> f64 + Send + Sync>),
+}
+
+impl FeatureTolerance {
+    /// Create a tolerance from a function of the feature index
+    pub fn from_fn(f: impl Fn(usize) -> f64 + Send + Sync + 'static) -> Self {
+        Self::Fn(Arc::new(f))
+    }
+
+    /// The tolerance of feature `i`.
+    ///
+    /// Panics if the tolerance is given as values and there is no value for `i`.
+    pub fn get(&self, i: usize) -> f64 {
+        match self {
+            Self::Values(values) => values[i],
+            Self::Fn(f) => f(i),
+        }
+    }
+
+    /// Check that a tolerance given as values has one for each of `n`
+    /// features. `option` names the tolerance in the error.
+    pub fn check(&self, option: &'static str, n: usize) -> Result<(), InputError> {
+        match self {
+            Self::Values(values) => check_count(option, n, values),
+            Self::Fn(_) => Ok(()),
+        }
+    }
+
+    /// The largest tolerance of the first `n` features
+    pub fn max(&self, n: usize) -> f64 {
+        (0..n).map(|i| self.get(i)).fold(0.0, f64::max)
+    }
+}
+
+impl From<Vec<f64>> for FeatureTolerance {
+    fn from(values: Vec<f64>) -> Self {
+        Self::Values(values)
+    }
+}
+
+impl fmt::Debug for FeatureTolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Values(values) => f.debug_tuple("Values").field(values).finish(),
+            Self::Fn(_) => f.write_str("Fn"),
+        }
+    }
+}
+
+impl PartialEq for FeatureTolerance {
+    // functions are only equal if they are the same function
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Values(a), Self::Values(b)) => a == b,
+            (Self::Fn(a), Self::Fn(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
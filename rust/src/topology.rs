@@ -0,0 +1,323 @@
+use crate::table::{Match, MatchTable};
+use geo_types::{Coord, MultiLineString};
+use std::collections::HashMap;
+
+/// Node and edge connectivity of a network of features.
+///
+/// Every feature is an edge between the nodes at its first and last
+/// coordinates, the start of its first part and the end of its last part.
+/// Features are connected when they share an endpoint with exactly the
+/// same coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topology {
+    /// Start and end node of each edge, `None` for empty features
+    edges: Vec<Option<(usize, usize)>>,
+    /// Edges connected to each edge, sorted and without the edge itself
+    neighbours: Vec<Vec<usize>>,
+    /// Edges ending at each node
+    incident: Vec<Vec<usize>>,
+    /// Coordinates of each node
+    nodes: Vec<Coord>,
+}
+
+impl Topology {
+    pub fn new(lines: &[MultiLineString]) -> Self {
+        let mut ids: HashMap<(u64, u64), usize> = HashMap::new();
+        let mut nodes = Vec::new();
+        let mut node_id = |c: &Coord| {
+            *ids.entry((c.x.to_bits(), c.y.to_bits()))
+                .or_insert_with(|| {
+                    nodes.push(*c);
+                    nodes.len() - 1
+                })
+        };
+
+        let edges = lines
+            .iter()
+            .map(|l| {
+                let first = l.0.first()?.0.first()?;
+                let last = l.0.last()?.0.last()?;
+                Some((node_id(first), node_id(last)))
+            })
+            .collect::<Vec<_>>();
+        let mut incident = vec![Vec::new(); nodes.len()];
+        edges.iter().enumerate().for_each(|(e, nodes)| {
+            if let Some((start, end)) = *nodes {
+                incident[start].push(e);
+                if end != start {
+                    incident[end].push(e);
+                }
+            }
+        });
+
+        let neighbours = edges
+            .iter()
+            .enumerate()
+            .map(|(e, nodes)| {
+                let mut adjacent = nodes
+                    .iter()
+                    .flat_map(|&(start, end)| incident[start].iter().chain(&incident[end]))
+                    .copied()
+                    .filter(|&other| other != e)
+                    .collect::<Vec<_>>();
+                adjacent.sort_unstable();
+                adjacent.dedup();
+                adjacent
+            })
+            .collect();
+
+        Self {
+            edges,
+            neighbours,
+            incident,
+            nodes,
+        }
+    }
+
+    /// Number of distinct endpoints
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Coordinates of node `n`
+    pub fn node_coord(&self, n: usize) -> Coord {
+        self.nodes[n]
+    }
+
+    /// Number of features
+    pub fn n_edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Start and end node of edge `e`, `None` if the feature is empty
+    pub fn edge_nodes(&self, e: usize) -> Option<(usize, usize)> {
+        self.edges[e]
+    }
+
+    /// Edges sharing an endpoint with edge `e`
+    pub fn neighbours(&self, e: usize) -> &[usize] {
+        &self.neighbours[e]
+    }
+
+    // edges other than `e` ending at node `n`
+    fn others_at(&self, e: usize, n: usize) -> impl Iterator<Item = usize> + '_ {
+        self.incident[n]
+            .iter()
+            .copied()
+            .filter(move |&other| other != e)
+    }
+}
+
+/// How matches are adjusted by the matches of their neighbours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TopologyWeights {
+    /// Fraction of the shared length added when all neighbours are supported
+    pub boost: f64,
+    /// Fraction of the shared length removed when no neighbours are supported
+    pub penalty: f64,
+}
+
+impl Default for TopologyWeights {
+    fn default() -> Self {
+        Self {
+            boost: 0.5,
+            penalty: 0.5,
+        }
+    }
+}
+
+/// A matched pair of source `i` and target `j` scored by the continuity
+/// of the match across neighbouring edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TopologyScore {
+    pub i: usize,
+    pub j: usize,
+    pub shared_len: f64,
+    /// Number of matched pairs of component lines of `i` and `j`
+    pub n_segments: usize,
+    /// Number of neighbours of `i` and of `j`
+    pub n_neighbours: usize,
+    /// Number of those neighbours that continue the match across a node
+    pub n_supported: usize,
+    /// Shared length after the boost or penalty
+    pub score: f64,
+}
+
+impl TopologyScore {
+    /// The pair as a [Match] with the score as its shared length
+    pub fn to_match(&self) -> Match {
+        Match {
+            i: self.i,
+            j: self.j,
+            shared_len: self.score,
+            n_segments: self.n_segments,
+        }
+    }
+}
+
+/// Score every pair of a [MatchTable] by whether neighbouring edges also match.
+///
+/// Each endpoint of `i` is paired with the nearer endpoint of `j`. A
+/// neighbour `i'` of `i` supports the pair `(i, j)` if, at a node it shares
+/// with `i`, it shares some length with a neighbour `j'` of `j` at the
+/// paired node, so the match continues across that node. Likewise for the
+/// neighbours of `j`. The score is the shared length scaled by
+/// `1 + boost * r - penalty * (1 - r)` where `r` is the fraction of
+/// supported neighbours. Pairs where neither feature has neighbours keep
+/// their shared length.
+pub fn topology_scores(
+    table: &MatchTable,
+    x_topology: &Topology,
+    y_topology: &Topology,
+    weights: TopologyWeights,
+) -> Vec<TopologyScore> {
+    // pairs that only touch share no length and continue nothing
+    let continues = |i: usize, j: usize| table.get(i, j).is_some_and(|l| l > 0.0);
+
+    table
+        .iter()
+        .map(|m| {
+            let x_neighbours = x_topology.neighbours(m.i);
+            let y_neighbours = y_topology.neighbours(m.j);
+            let n_neighbours = x_neighbours.len() + y_neighbours.len();
+
+            let paired = paired_nodes(x_topology, m.i, y_topology, m.j);
+            let x_supported = x_neighbours.iter().filter(|&&i| {
+                paired.iter().any(|&(a, b)| {
+                    x_topology.incident[a].contains(&i)
+                        && y_topology.others_at(m.j, b).any(|j| continues(i, j))
+                })
+            });
+            let y_supported = y_neighbours.iter().filter(|&&j| {
+                paired.iter().any(|&(a, b)| {
+                    y_topology.incident[b].contains(&j)
+                        && x_topology.others_at(m.i, a).any(|i| continues(i, j))
+                })
+            });
+            let n_supported = x_supported.count() + y_supported.count();
+
+            let factor = if n_neighbours > 0 {
+                let r = n_supported as f64 / n_neighbours as f64;
+                1.0 + weights.boost * r - weights.penalty * (1.0 - r)
+            } else {
+                1.0
+            };
+
+            TopologyScore {
+                i: m.i,
+                j: m.j,
+                shared_len: m.shared_len,
+                n_segments: m.n_segments,
+                n_neighbours,
+                n_supported,
+                score: m.shared_len * factor,
+            }
+        })
+        .collect()
+}
+
+// The endpoints of `i` each paired with an endpoint of `j`, choosing
+// whichever pairing of start and end nodes is closer in total
+fn paired_nodes(x: &Topology, i: usize, y: &Topology, j: usize) -> Vec<(usize, usize)> {
+    let (Some((xs, xe)), Some((ys, ye))) = (x.edge_nodes(i), y.edge_nodes(j)) else {
+        return Vec::new();
+    };
+    let dist = |a: usize, b: usize| {
+        let d = x.node_coord(a) - y.node_coord(b);
+        d.x.hypot(d.y)
+    };
+    if dist(xs, ys) + dist(xe, ye) <= dist(xs, ye) + dist(xe, ys) {
+        vec![(xs, ys), (xe, ye)]
+    } else {
+        vec![(xs, ye), (xe, ys)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::Match;
+    use geo_types::LineString;
+
+    fn feature(coords: &[(f64, f64)]) -> MultiLineString {
+        MultiLineString::new(vec![LineString::from(coords.to_vec())])
+    }
+
+    fn score(scores: &[TopologyScore], i: usize, j: usize) -> TopologyScore {
+        *scores.iter().find(|s| s.i == i && s.j == j).unwrap()
+    }
+
+    #[test]
+    fn side_road_at_a_junction_is_penalised() {
+        // a main road M0, M1, M2 and its copy T0, T1, T2 1 m to the side.
+        // the side road S leaves the T1/T2 junction, runs 3 m beside M1
+        // for 18 m and ends where a side street R begins
+        let x = vec![
+            feature(&[(-100.0, 0.0), (0.0, 0.0)]),
+            feature(&[(0.0, 0.0), (100.0, 0.0)]),
+            feature(&[(100.0, 0.0), (200.0, 0.0)]),
+        ];
+        let y = vec![
+            feature(&[(-100.0, 1.0), (0.0, 1.0)]),
+            feature(&[(0.0, 1.0), (100.0, 1.0)]),
+            feature(&[(100.0, 1.0), (200.0, 1.0)]),
+            feature(&[(100.0, 1.0), (98.0, -3.0), (80.0, -3.0), (70.0, -30.0)]),
+            feature(&[(70.0, -30.0), (70.0, -60.0)]),
+        ];
+        let table = [
+            (0, 0, 100.0),
+            (1, 1, 100.0),
+            (1, 3, 18.0),
+            (2, 2, 100.0),
+            // M2 touches the end of T1 and shares nothing
+            (2, 1, 0.0),
+        ]
+        .into_iter()
+        .map(|(i, j, shared_len)| Match {
+            i,
+            j,
+            shared_len,
+            n_segments: 1,
+        })
+        .collect::<MatchTable>();
+
+        let scores = topology_scores(
+            &table,
+            &Topology::new(&x),
+            &Topology::new(&y),
+            TopologyWeights::default(),
+        );
+
+        // M0 and M2 continue onto T0 and T2, S is the only unsupported neighbour
+        let main = score(&scores, 1, 1);
+        assert_eq!((main.n_supported, main.n_neighbours), (4, 5));
+        assert!((main.score - 130.0).abs() < 1e-9);
+
+        // only the continuation onto T2 supports the side road
+        let side = score(&scores, 1, 3);
+        assert_eq!((side.n_supported, side.n_neighbours), (2, 5));
+        assert!(side.score < side.shared_len);
+    }
+
+    #[test]
+    fn features_without_neighbours_keep_their_length() {
+        let x = vec![feature(&[(0.0, 0.0), (10.0, 0.0)])];
+        let y = vec![feature(&[(0.0, 1.0), (10.0, 1.0)])];
+        let table = [Match {
+            i: 0,
+            j: 0,
+            shared_len: 10.0,
+            n_segments: 1,
+        }]
+        .into_iter()
+        .collect::<MatchTable>();
+        let scores = topology_scores(
+            &table,
+            &Topology::new(&x),
+            &Topology::new(&y),
+            TopologyWeights::default(),
+        );
+        assert_eq!(scores[0].n_neighbours, 0);
+        assert_eq!(scores[0].score, 10.0);
+    }
+}
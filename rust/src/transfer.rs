@@ -0,0 +1,284 @@
+use crate::aggregate::{Aggregation, Aggregator, NumericAggregation};
+use crate::matcher::Matcher;
+use arrow::array::{ArrayRef, AsArray, Float64Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Float64Type, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use geo_types::MultiLineString;
+use geoarrow::array::{from_arrow_array, LineStringArray, MultiLineStringArray};
+use std::sync::Arc;
+
+/// How one source column is transferred to the targets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnRule {
+    pub column: String,
+    pub aggregation: Aggregation,
+}
+
+/// Transfers attribute columns of a source [RecordBatch] to a target
+/// [RecordBatch] by the length of the matched lines they share.
+///
+/// Numeric columns are added to the targets as `<column>_ext` or
+/// `<column>_int` for [Aggregation::Extensive] and [Aggregation::Intensive]
+/// rules. [Aggregation::Categorical] columns add a `<column>_<category>`
+/// column with the proportion of each category. Targets without matched
+/// values are null. See [Aggregator].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeTransfer {
+    pub geometry_column: String,
+    pub rules: Vec<ColumnRule>,
+}
+
+impl Default for AttributeTransfer {
+    fn default() -> Self {
+        Self {
+            geometry_column: "geometry".to_string(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl AttributeTransfer {
+    /// Create a transfer of no columns reading geometries from the
+    /// `geometry` column of both batches.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read geometries from `name` instead. The column must be a geoarrow
+    /// `LineString` or `MultiLineString` array.
+    pub fn geometry_column(mut self, name: &str) -> Self {
+        self.geometry_column = name.to_string();
+        self
+    }
+
+    /// Transfer the source column `name` using `aggregation`. Numeric rules
+    /// need a column that can be cast to `Float64` and categorical rules
+    /// one that can be cast to `Utf8`.
+    pub fn column(mut self, name: &str, aggregation: Aggregation) -> Self {
+        self.rules.push(ColumnRule {
+            column: name.to_string(),
+            aggregation,
+        });
+        self
+    }
+
+    /// Match the lines of `source` to those of `target` with `matcher` and
+    /// return `target` with the aggregated source columns appended.
+    ///
+    /// Returns a [ArrowError::SchemaError] if a column is missing or an
+    /// appended column has the name of an existing one.
+    pub fn transfer(
+        &self,
+        matcher: &Matcher,
+        source: &RecordBatch,
+        target: &RecordBatch,
+    ) -> Result<RecordBatch, ArrowError> {
+        let x = self.read_features(source)?;
+        let y = self.read_features(target)?;
+        let (table, x_lens, y_lens) =
+            matcher.find_candidates_with_lengths(x.into_iter(), y.into_iter());
+        let aggregator = Aggregator::new(&table, &x_lens, &y_lens);
+
+        let schema = target.schema();
+        let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+        let mut columns = target.columns().to_vec();
+        let mut push = |name: String, values: Vec<Option<f64>>| {
+            if fields.iter().any(|f| f.name() == &name) {
+                return Err(ArrowError::SchemaError(format!(
+                    "target already has a column `{name}`"
+                )));
+            }
+            fields.push(Arc::new(Field::new(name, DataType::Float64, true)));
+            columns.push(Arc::new(Float64Array::from(values)) as ArrayRef);
+            Ok(())
+        };
+
+        for rule in &self.rules {
+            let column = source.column_by_name(&rule.column).ok_or_else(|| {
+                ArrowError::SchemaError(format!("source has no column `{}`", rule.column))
+            })?;
+            match rule.aggregation {
+                Aggregation::Extensive | Aggregation::Intensive => {
+                    let values = cast(column, &DataType::Float64)?;
+                    let values = values
+                        .as_primitive::<Float64Type>()
+                        .iter()
+                        .collect::<Vec<_>>();
+                    let (how, suffix) = match rule.aggregation {
+                        Aggregation::Extensive => (NumericAggregation::Extensive, "ext"),
+                        _ => (NumericAggregation::Intensive, "int"),
+                    };
+                    push(
+                        format!("{}_{}", rule.column, suffix),
+                        aggregator.numeric(&values, how),
+                    )?;
+                }
+                Aggregation::Categorical => {
+                    let values = cast(column, &DataType::Utf8)?;
+                    let values = values.as_string::<i32>().iter().collect::<Vec<_>>();
+                    let res = aggregator.categorical(&values);
+                    res.categories
+                        .iter()
+                        .zip(res.proportions)
+                        .try_for_each(|(category, p)| {
+                            push(format!("{}_{}", rule.column, category), p)
+                        })?;
+                }
+            }
+        }
+
+        let schema = Schema::new_with_metadata(fields, schema.metadata().clone());
+        RecordBatch::try_new(Arc::new(schema), columns)
+    }
+
+    // the features of the geometry column of `batch`
+    fn read_features(&self, batch: &RecordBatch) -> Result<Vec<MultiLineString>, ArrowError> {
+        let schema = batch.schema();
+        let (idx, field) = schema
+            .column_with_name(&self.geometry_column)
+            .ok_or_else(|| {
+                ArrowError::SchemaError(format!("no geometry column `{}`", self.geometry_column))
+            })?;
+        let arr = from_arrow_array(batch.column(idx).as_ref(), field)
+            .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+
+        let arr = arr.as_any();
+        if let Some(lns) = arr.downcast_ref::<LineStringArray<i32>>() {
+            Ok(lns.iter_geo_values().map(Into::into).collect())
+        } else if let Some(lns) = arr.downcast_ref::<LineStringArray<i64>>() {
+            Ok(lns.iter_geo_values().map(Into::into).collect())
+        } else if let Some(mlns) = arr.downcast_ref::<MultiLineStringArray<i32>>() {
+            Ok(mlns.iter_geo_values().collect())
+        } else if let Some(mlns) = arr.downcast_ref::<MultiLineStringArray<i64>>() {
+            Ok(mlns.iter_geo_values().collect())
+        } else {
+            Err(ArrowError::InvalidArgumentError(
+                "geometries must be LineString or MultiLineString".to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatchOptions;
+    use arrow::array::{Int32Array, StringArray};
+    use geo_types::{line_string, LineString};
+    use geoarrow::GeometryArrayTrait;
+
+    fn batch(lines: Vec<LineString>, cols: Vec<(&str, ArrayRef)>) -> RecordBatch {
+        let arr: LineStringArray<i32> = lines.as_slice().into();
+        let mut fields = vec![arr.extension_field().as_ref().clone().with_name("geometry")];
+        let mut columns = vec![arr.to_array_ref()];
+        cols.into_iter().for_each(|(name, col)| {
+            fields.push(Field::new(name, col.data_type().clone(), true));
+            columns.push(col);
+        });
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+
+    // sources 0 and 1 cover the first target end to end. source 2 and the
+    // second target are far from anything else
+    fn batches() -> (RecordBatch, RecordBatch) {
+        let source = batch(
+            vec![
+                line_string![(x: 0., y: 0.), (x: 10., y: 0.)],
+                line_string![(x: 10., y: 0.), (x: 20., y: 0.)],
+                line_string![(x: 0., y: 50.), (x: 20., y: 50.)],
+            ],
+            vec![
+                (
+                    "count",
+                    Arc::new(Int32Array::from(vec![100, 50, 7])) as ArrayRef,
+                ),
+                (
+                    "speed",
+                    Arc::new(Float64Array::from(vec![30.0, 10.0, 20.0])),
+                ),
+                ("kind", Arc::new(StringArray::from(vec!["a", "b", "b"]))),
+            ],
+        );
+        let target = batch(
+            vec![
+                line_string![(x: 0., y: 1.), (x: 20., y: 1.)],
+                line_string![(x: 100., y: 0.), (x: 110., y: 0.)],
+            ],
+            vec![("id", Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef)],
+        );
+        (source, target)
+    }
+
+    fn column(batch: &RecordBatch, name: &str) -> Vec<Option<f64>> {
+        batch
+            .column_by_name(name)
+            .unwrap_or_else(|| panic!("no column `{name}`"))
+            .as_primitive::<Float64Type>()
+            .iter()
+            .collect()
+    }
+
+    #[test]
+    fn columns_are_appended_to_the_targets() {
+        let (source, target) = batches();
+        let res = AttributeTransfer::new()
+            .column("count", Aggregation::Extensive)
+            .column("speed", Aggregation::Intensive)
+            .column("kind", Aggregation::Categorical)
+            .transfer(&MatchOptions::new(2.0, 10.0).build(), &source, &target)
+            .unwrap();
+
+        let names = res
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "geometry",
+                "id",
+                "count_ext",
+                "speed_int",
+                "kind_a",
+                "kind_b"
+            ]
+        );
+        assert_eq!(column(&res, "count_ext"), vec![Some(150.0), None]);
+        assert_eq!(column(&res, "speed_int"), vec![Some(20.0), None]);
+        assert_eq!(column(&res, "kind_a"), vec![Some(0.5), None]);
+        assert_eq!(column(&res, "kind_b"), vec![Some(0.5), None]);
+    }
+
+    #[test]
+    fn missing_columns_are_errors() {
+        let (source, target) = batches();
+        let matcher = MatchOptions::new(2.0, 10.0).build();
+
+        let res = AttributeTransfer::new()
+            .geometry_column("geom")
+            .transfer(&matcher, &source, &target);
+        assert!(matches!(res, Err(ArrowError::SchemaError(_))));
+
+        let res = AttributeTransfer::new()
+            .column("volume", Aggregation::Extensive)
+            .transfer(&matcher, &source, &target);
+        assert!(matches!(res, Err(ArrowError::SchemaError(_))));
+    }
+
+    #[test]
+    fn appended_columns_must_have_new_names() {
+        let (source, target) = batches();
+        let target = batch(
+            vec![line_string![(x: 0., y: 1.), (x: 20., y: 1.)]],
+            vec![("kind_a", target.column(1).slice(0, 1))],
+        );
+        let res = AttributeTransfer::new()
+            .column("kind", Aggregation::Categorical)
+            .transfer(&MatchOptions::new(2.0, 10.0).build(), &source, &target);
+        assert!(matches!(res, Err(ArrowError::SchemaError(_))));
+    }
+}
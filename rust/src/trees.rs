@@ -1,22 +1,36 @@
-use crate::structs::TarLine;
+use crate::angle::bearing;
+use crate::structs::{feature_lines, CrsType, TarLine};
+use geo_types::MultiLineString;
 use rstar::primitives::{CachedEnvelope, GeomWithData};
 
-/// Create an RTree from LineStringArray<i64>
+/// A component line of a source feature with the index of the
+/// feature, the index of the line within it, and its bearing
+pub type SourceTreeItem = GeomWithData<CachedEnvelope<geo_types::Line>, (usize, usize, f64)>;
+/// A component line of a target feature with the index of the
+/// feature, the index of the line within it, and its bearing
+pub type TargetTreeItem = GeomWithData<CachedEnvelope<TarLine>, (usize, usize, f64)>;
+
+pub type SourceTree = rstar::RTree<SourceTreeItem>;
+pub type TargetTree = rstar::RTree<TargetTreeItem>;
+
+/// Create an RTree from LineStrings or MultiLineStrings
 ///
-/// Creates an R* Tree using cached envelopes for each Line in a LineString.
-/// In addition to the envelope, it stores the slope, and the index of the LineString.
+/// Creates an R* Tree using cached envelopes for each Line in a feature.
+/// In addition to the envelope, it stores the bearing, the index of the feature
+/// and the index of the Line within the feature, counted across all parts.
 pub fn create_source_rtree(
-    x: impl Iterator<Item = geo_types::LineString>,
-) -> rstar::RTree<GeomWithData<CachedEnvelope<geo_types::Line>, (usize, f64)>> {
+    x: impl Iterator<Item = impl Into<MultiLineString>>,
+    crs_type: CrsType,
+) -> SourceTree {
     let to_insert = x
         .enumerate()
         .flat_map(|(i, xi)| {
-            let components = xi
-                .lines()
-                .map(|li| {
-                    let slope = li.slope();
+            let components = feature_lines(&xi.into())
+                .enumerate()
+                .map(|(k, li)| {
+                    let bearing = bearing(&li, crs_type);
                     let env = CachedEnvelope::new(li);
-                    GeomWithData::new(env, (i, slope))
+                    GeomWithData::new(env, (i, k, bearing))
                 })
                 .collect::<Vec<GeomWithData<_, _>>>();
             components
@@ -26,20 +40,27 @@ pub fn create_source_rtree(
     rstar::RTree::bulk_load(to_insert)
 }
 
+/// Create an RTree of target lines
+///
+/// Each Line is stored as a [TarLine] whose envelope is padded by `dist`, the
+/// distance tolerance of its feature given the index of the feature.
+/// Use `|_| tolerance` to pad every line by the same distance.
 pub fn create_target_rtree(
-    y: impl Iterator<Item = geo_types::LineString>,
-    dist: f64,
-) -> rstar::RTree<GeomWithData<CachedEnvelope<TarLine>, (usize, f64)>> {
+    y: impl Iterator<Item = impl Into<MultiLineString>>,
+    dist: impl Fn(usize) -> f64,
+    crs_type: CrsType,
+) -> TargetTree {
     let to_insert = y
         .enumerate()
         .flat_map(|(i, yi)| {
-            let components = yi
-                .lines()
-                .map(|li| {
-                    let tl = TarLine(li, dist);
-                    let slope = li.slope();
+            let dist = dist(i);
+            let components = feature_lines(&yi.into())
+                .enumerate()
+                .map(|(k, li)| {
+                    let tl = TarLine(li, dist, crs_type);
+                    let bearing = bearing(&li, crs_type);
                     let env = CachedEnvelope::new(tl);
-                    GeomWithData::new(env, (i, slope))
+                    GeomWithData::new(env, (i, k, bearing))
                 })
                 .collect::<Vec<GeomWithData<_, _>>>();
             components